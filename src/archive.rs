@@ -0,0 +1,193 @@
+use anyhow::{bail, Context, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    path::Path,
+};
+use tar::Archive;
+
+use crate::feed::member_name;
+
+const AR_MAGIC: &[u8] = b"!<arch>\n";
+
+/// Container of a package file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// tar.gz, as opkg reads it
+    Ipk,
+    /// `ar`, as dpkg reads it
+    Deb,
+}
+
+impl Format {
+    /// The format a file name asks for, by its extension.
+    pub fn of(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "ipk" => Some(Format::Ipk),
+            "deb" => Some(Format::Deb),
+            _ => None,
+        }
+    }
+}
+
+/// The three members of a package. Control and data are gzip compressed
+/// tar archives whatever the package they came from used.
+pub struct Members {
+    pub debian_binary: Vec<u8>,
+    pub control: Vec<u8>,
+    pub data: Vec<u8>,
+}
+
+/// Content of one member to write, read as it is written.
+pub struct Member<'a> {
+    pub size: u64,
+    pub content: Box<dyn Read + 'a>,
+}
+
+impl<'a> Member<'a> {
+    pub fn bytes(content: &'a [u8]) -> Self {
+        Member {
+            size: content.len() as u64,
+            content: Box::new(content),
+        }
+    }
+
+    pub fn file(path: &Path) -> Result<Self> {
+        let file = File::open(path).context(format!("Could not open {}", path.display()))?;
+        Ok(Member {
+            size: file.metadata()?.len(),
+            content: Box::new(file),
+        })
+    }
+}
+
+impl Members {
+    pub fn to_write(&self) -> [Member<'_>; 3] {
+        [
+            Member::bytes(&self.debian_binary),
+            Member::bytes(&self.control),
+            Member::bytes(&self.data),
+        ]
+    }
+}
+
+/// Uncompressed content of a `control.tar.*` or `data.tar.*` member.
+fn decompress(name: &str, content: &[u8]) -> Result<Vec<u8>> {
+    let mut tar = Vec::new();
+    match name.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("gz") => {
+            GzDecoder::new(content).read_to_end(&mut tar)?;
+        }
+        Some("xz") => lzma_rs::xz_decompress(&mut &content[..], &mut tar)?,
+        Some("zst") => {
+            ruzstd::decoding::StreamingDecoder::new(content)?.read_to_end(&mut tar)?;
+        }
+        Some("tar") => tar = content.to_vec(),
+        _ => bail!("{} uses a compression that is not supported", name),
+    }
+    Ok(tar)
+}
+
+/// Compress `content` the way the package archives are.
+pub fn gzip(content: &[u8]) -> Result<Vec<u8>> {
+    let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+    enc.write_all(content)?;
+    Ok(enc.finish()?)
+}
+
+/// Take a `control.tar.*` or `data.tar.*` member as tar.gz.
+fn as_tar_gz(name: &str, content: Vec<u8>) -> Result<Vec<u8>> {
+    if name.ends_with(".tar.gz") {
+        return Ok(content);
+    }
+    let tar = decompress(name, &content).context(format!("Could not decompress {}", name))?;
+    gzip(&tar)
+}
+
+/// Read the members of an ipk or a deb, telling them apart by content.
+pub fn read(path: &Path) -> Result<Members> {
+    let content = fs::read(path).context(format!("Could not read {}", path.display()))?;
+    let mut debian_binary = None;
+    let mut control = None;
+    let mut data = None;
+    let mut take = |name: &str, content: Vec<u8>| -> Result<()> {
+        if name == "debian-binary" {
+            debian_binary = Some(content);
+        } else if name.starts_with("control.tar") {
+            control = Some(as_tar_gz(name, content)?);
+        } else if name.starts_with("data.tar") {
+            data = Some(as_tar_gz(name, content)?);
+        }
+        Ok(())
+    };
+    if content.starts_with(AR_MAGIC) {
+        let mut archive = ar::Archive::new(&content[..]);
+        while let Some(entry) = archive.next_entry() {
+            let mut entry = entry.context(format!("Could not read {}", path.display()))?;
+            // GNU ar ends names with a slash
+            let name = String::from_utf8_lossy(entry.header().identifier())
+                .trim_end_matches('/')
+                .to_owned();
+            let mut member = Vec::new();
+            entry.read_to_end(&mut member)?;
+            take(&name, member)?;
+        }
+    } else {
+        let mut archive = Archive::new(GzDecoder::new(&content[..]));
+        for entry in archive.entries().context(format!("Could not read {}", path.display()))? {
+            let mut entry = entry.context(format!("Could not read {}", path.display()))?;
+            let name = member_name(&entry)?;
+            let mut member = Vec::new();
+            entry.read_to_end(&mut member)?;
+            take(&name, member)?;
+        }
+    }
+    match (debian_binary, control, data) {
+        (Some(debian_binary), Some(control), Some(data)) => Ok(Members {
+            debian_binary,
+            control,
+            data,
+        }),
+        _ => bail!(
+            "{} lacks one of debian-binary, control.tar and data.tar",
+            path.display()
+        ),
+    }
+}
+
+/// Write a package of `format` to `path` from its members debian-binary,
+/// control.tar.gz and data.tar.gz, in this order. The members are streamed,
+/// not held in memory.
+pub fn write(path: &Path, format: Format, members: [Member<'_>; 3]) -> Result<()> {
+    let named = ["debian-binary", "control.tar.gz", "data.tar.gz"].into_iter().zip(members);
+    let file = File::create(path).context(format!("Could not create {}", path.display()))?;
+    match format {
+        Format::Ipk => {
+            let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+            for (name, member) in named {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(member.size);
+                header.set_uid(0);
+                header.set_gid(0);
+                header.set_mode(0o644);
+                header.set_cksum();
+                tar.append_data(&mut header, name, member.content)
+                    .context(format!("Could not write {}", path.display()))?;
+            }
+            tar.into_inner()
+                .and_then(|enc| enc.finish())
+                .context(format!("Could not write {}", path.display()))?;
+        }
+        Format::Deb => {
+            let mut ar = ar::Builder::new(file);
+            for (name, member) in named {
+                let mut header = ar::Header::new(name.as_bytes().to_vec(), member.size);
+                header.set_mode(0o100644);
+                ar.append(&header, member.content)
+                    .context(format!("Could not write {}", path.display()))?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use anyhow::{bail, Context, Result};
+use log::{error, info};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{build_all, control::ControlFile, feed, output_dir, output_paths, progress::Progress, project};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Failed,
+}
+
+/// Outcome of one package in a batch build. A project that fails before
+/// producing any package gets a single row with the error.
+#[derive(Serialize)]
+pub struct BatchResult {
+    pub project: PathBuf,
+    pub status: Status,
+    pub package: String,
+    pub version: String,
+    pub output: Option<PathBuf>,
+    pub size: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl BatchResult {
+    fn failed(project: &Path, error: String) -> BatchResult {
+        BatchResult {
+            project: project.to_owned(),
+            status: Status::Failed,
+            package: String::new(),
+            version: String::new(),
+            output: None,
+            size: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// Expand directories in `paths` to the project files they contain, other
+/// `*.toml` files are skipped. Files are taken as they are.
+pub fn collect_projects(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut projects = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            projects.push(path.clone());
+            continue;
+        }
+        let mut found: Vec<PathBuf> = fs::read_dir(path)
+            .context(format!("Could not read project folder {}", path.display()))?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == "toml") && project::is_project(p))
+            .collect();
+        found.sort();
+        projects.extend(found);
+    }
+    Ok(projects)
+}
+
+/// Fail if two projects would write the same package file, as builds
+/// running in parallel would overwrite each other. Projects that cannot be
+/// loaded are left for the build to report.
+pub fn check_outputs(projects: &[PathBuf]) -> Result<()> {
+    let mut owners: HashMap<PathBuf, &Path> = HashMap::new();
+    for project in projects {
+        let Ok(paths) = project::load(project).and_then(|data| output_paths(&data)) else {
+            continue;
+        };
+        for path in paths {
+            if let Some(other) = owners.insert(path.clone(), project) {
+                if other != project.as_path() {
+                    bail!(
+                        "{} and {} both write {}, give them different output names",
+                        other.display(),
+                        project.display(),
+                        path.display()
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Results of one project, and its output folder if it asks for a feed index.
+type ProjectResults = (Vec<BatchResult>, Option<PathBuf>);
+
+/// Build one project. The feed index is left to [`run`], which writes it
+/// once all builds are done.
+fn build_project(project: &Path) -> ProjectResults {
+    let built = project::load(project).and_then(|data| {
+        let index = if data.index_feed { Some(output_dir(&data)?) } else { None };
+        Ok((build_all(&data, &Progress::default())?, index))
+    });
+    match built {
+        Ok((built, index)) => {
+            let results = built
+                .into_iter()
+                .map(|package| {
+                    let fields = ControlFile::parse(&package.control);
+                    BatchResult {
+                        project: project.to_owned(),
+                        status: Status::Ok,
+                        package: fields.get("Package").unwrap_or_default().to_owned(),
+                        version: fields.get("Version").unwrap_or_default().to_owned(),
+                        size: Some(package.size),
+                        output: Some(package.path),
+                        error: None,
+                    }
+                })
+                .collect();
+            (results, index)
+        }
+        Err(e) => {
+            error!("Building {} failed: {:#}", project.display(), e);
+            (vec![BatchResult::failed(project, format!("{:#}", e))], None)
+        }
+    }
+}
+
+/// Build every project on up to `jobs` threads. Failures don't stop the
+/// batch; results are returned in the order of `projects`. Feed indexes are
+/// written after all builds, once per output folder, so projects sharing a
+/// folder don't race for its index.
+pub fn run(projects: &[PathBuf], jobs: usize) -> Vec<BatchResult> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<(usize, ProjectResults)>> = Mutex::new(Vec::new());
+    thread::scope(|s| {
+        for _ in 0..jobs.clamp(1, projects.len().max(1)) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(project) = projects.get(i) else {
+                    break;
+                };
+                info!("Building {}", project.display());
+                let result = build_project(project);
+                results.lock().unwrap().push((i, result));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _)| *i);
+    let mut indexes: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    for (i, (_, index)) in &results {
+        if let Some(dir) = index {
+            indexes.entry(dir.clone()).or_default().push(projects[*i].clone());
+        }
+    }
+    let mut results: Vec<BatchResult> = results.into_iter().flat_map(|(_, (r, _))| r).collect();
+    for (dir, owners) in indexes {
+        info!("Writing feed index of {}", dir.display());
+        if let Err(e) = feed::write_index(&dir) {
+            let e = format!("Error writing feed index of {}: {:#}", dir.display(), e);
+            error!("{}", e);
+            results.extend(owners.iter().map(|project| BatchResult::failed(project, e.clone())));
+        }
+    }
+    results
+}
+
+/// Human readable summary, one row per package.
+pub fn table(results: &[BatchResult]) -> String {
+    let rows: Vec<[String; 5]> = results
+        .iter()
+        .map(|r| {
+            [
+                if r.package.is_empty() {
+                    r.project.display().to_string()
+                } else {
+                    r.package.clone()
+                },
+                r.version.clone(),
+                r.output
+                    .as_ref()
+                    .map(|o| o.display().to_string())
+                    .unwrap_or_default(),
+                r.size.map(|s| s.to_string()).unwrap_or_default(),
+                match &r.error {
+                    None => "ok".to_owned(),
+                    Some(e) => format!("FAILED: {}", e),
+                },
+            ]
+        })
+        .collect();
+    let header = ["PACKAGE", "VERSION", "OUTPUT", "SIZE", "STATUS"].map(|h| h.to_owned());
+    let mut widths = [0; 5];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let mut table = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        table.push_str(line.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+pub fn to_json(results: &[BatchResult]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(results)?)
+}
@@ -0,0 +1,69 @@
+use ipkbuilder::{cargo::CargoProject, console, make_package, output_dir, progress::Progress};
+use anyhow::Context;
+use std::{env, fs, path::PathBuf, process::ExitCode};
+
+const USAGE: &str = "Usage:
+    cargo ipk [--no-build] [--manifest-path <Cargo.toml>] [--target <triple>]
+                                  build the release binaries and package
+                                  them into target/ipk, with the control
+                                  file filled from Cargo.toml and the
+                                  settings of [package.metadata.ipk],
+                                  --target builds for another triple and
+                                  takes the architecture from it";
+
+fn main() -> ExitCode {
+    console::init();
+    // cargo runs `cargo-ipk ipk <args>`
+    let args: Vec<String> = env::args().skip(1).skip_while(|a| a == "ipk").collect();
+    let mut manifest = PathBuf::from("Cargo.toml");
+    let mut build = true;
+    let mut target = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-build" => build = false,
+            "--manifest-path" => match args.next() {
+                Some(path) => manifest = PathBuf::from(path),
+                None => {
+                    eprintln!("--manifest-path needs a path");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--target" => match args.next() {
+                Some(triple) => target = Some(triple.to_owned()),
+                None => {
+                    eprintln!("--target needs a target triple");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let result = CargoProject::load(&manifest).and_then(|mut project| {
+        project.target = target;
+        if build {
+            project.build()?;
+        }
+        let data = project.builder()?;
+        let output = output_dir(&data)?;
+        fs::create_dir_all(&output).context(format!("Could not create {}", output.display()))?;
+        make_package(&data, &Progress::default())
+    });
+    match result {
+        Ok(package) => {
+            println!("{}", package);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use anyhow::{bail, Context, Result};
+use log::info;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use toml::{Table, Value};
+
+use crate::ui::{IpkBuilder, ScriptSource};
+
+/// File name of packages built from a Cargo project.
+pub const OUTPUT_NAME: &str = "${PACKAGE}_${VERSION}_${ARCH}.ipk";
+
+/// A `Cargo.toml` read for packaging.
+pub struct CargoProject {
+    /// directory of `Cargo.toml`
+    pub dir: PathBuf,
+    pub target_dir: PathBuf,
+    /// target triple to build for, e.g. `armv7-unknown-linux-gnueabihf`,
+    /// the host if none
+    pub target: Option<String>,
+    package: Table,
+    /// `[workspace.package]` of the enclosing workspace, for inherited fields
+    workspace: Option<Table>,
+    bins: Vec<String>,
+}
+
+fn read_toml(path: &Path) -> Result<Table> {
+    let text = fs::read_to_string(path).context(format!("Could not read {}", path.display()))?;
+    toml::from_str(&text).context(format!("{} is not valid TOML", path.display()))
+}
+
+/// Architecture of the packages built for `triple`, its first component,
+/// e.g. `armv7` for `armv7-unknown-linux-gnueabihf`.
+fn triple_arch(triple: &str) -> Result<&str> {
+    match triple.split_once('-') {
+        Some((arch, _)) if !arch.is_empty() => Ok(arch),
+        _ => bail!("{} is not a target triple", triple),
+    }
+}
+
+/// Cargo versions as package versions: a pre-release sorts before its
+/// release with `~`, `1.0.0-rc.1` becomes `1.0.0~rc.1`.
+fn package_version(version: &str) -> String {
+    version.replace('-', "~")
+}
+
+/// Crate names as package names, which use `-` rather than `_`.
+fn package_name(name: &str) -> String {
+    name.replace('_', "-")
+}
+
+/// Names of the binaries of a package, the way cargo finds them: the
+/// `[[bin]]` tables, then `src/main.rs` and `src/bin/*.rs` or
+/// `src/bin/*/main.rs` unless `autobins = false`.
+fn bins(dir: &Path, table: &Table, package: &Table) -> Result<Vec<String>> {
+    let mut bins = Vec::new();
+    // paths of the [[bin]] tables, not to be found a second time
+    let mut explicit = Vec::new();
+    if let Some(Value::Array(tables)) = table.get("bin") {
+        for bin in tables {
+            if let Some(name) = bin.get("name").and_then(|n| n.as_str()) {
+                bins.push(name.to_owned());
+            }
+            if let Some(path) = bin.get("path").and_then(|p| p.as_str()) {
+                explicit.push(dir.join(path));
+            }
+        }
+    }
+    if package.get("autobins").and_then(|a| a.as_bool()) == Some(false) {
+        return Ok(bins);
+    }
+    let mut found = Vec::new();
+    if let Some(name) = package.get("name").and_then(|n| n.as_str()) {
+        found.push((name.to_owned(), dir.join("src/main.rs")));
+    }
+    let bin_dir = dir.join("src/bin");
+    if bin_dir.is_dir() {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&bin_dir).context(format!("Could not read {}", bin_dir.display()))? {
+            entries.push(entry?.path());
+        }
+        entries.sort();
+        for path in entries {
+            let Some(stem) = path.file_stem() else {
+                continue;
+            };
+            let stem = stem.to_string_lossy().into_owned();
+            if path.extension().is_some_and(|e| e == "rs") {
+                found.push((stem, path));
+            } else {
+                found.push((stem, path.join("main.rs")));
+            }
+        }
+    }
+    for (name, path) in found {
+        if path.is_file() && !explicit.contains(&path) && !bins.contains(&name) {
+            bins.push(name);
+        }
+    }
+    Ok(bins)
+}
+
+/// The nearest `Cargo.toml` above `dir` with a `[workspace]` table.
+fn find_workspace(dir: &Path) -> Result<Option<(PathBuf, Table)>> {
+    for dir in dir.ancestors() {
+        let manifest = dir.join("Cargo.toml");
+        if manifest.is_file() {
+            let table = read_toml(&manifest)?;
+            if let Some(Value::Table(workspace)) = table.get("workspace") {
+                return Ok(Some((dir.to_owned(), workspace.clone())));
+            }
+        }
+    }
+    Ok(None)
+}
+
+impl CargoProject {
+    pub fn load(manifest: &Path) -> Result<CargoProject> {
+        let table = read_toml(manifest)?;
+        let Some(Value::Table(package)) = table.get("package") else {
+            bail!("{} has no [package]", manifest.display());
+        };
+        let dir = match manifest.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+            _ => PathBuf::from("."),
+        };
+        let workspace = find_workspace(&dir)?;
+        let target_dir = match env::var_os("CARGO_TARGET_DIR") {
+            Some(target_dir) => PathBuf::from(target_dir),
+            None => workspace.as_ref().map_or(dir.as_path(), |(root, _)| root).join("target"),
+        };
+        let bins = bins(&dir, &table, package)?;
+        Ok(CargoProject {
+            dir,
+            target_dir,
+            target: None,
+            package: package.clone(),
+            workspace: workspace.and_then(|(_, w)| w.get("package")?.as_table().cloned()),
+            bins,
+        })
+    }
+
+    /// A `[package]` value, following `field.workspace = true`.
+    fn field(&self, key: &str) -> Result<Option<&Value>> {
+        let value = self.package.get(key);
+        let inherited = value
+            .and_then(|v| v.get("workspace"))
+            .and_then(|w| w.as_bool())
+            .unwrap_or(false);
+        if !inherited {
+            return Ok(value);
+        }
+        match self.workspace.as_ref().and_then(|w| w.get(key)) {
+            Some(value) => Ok(Some(value)),
+            None => bail!("package.{} is inherited, but the workspace does not set it", key),
+        }
+    }
+
+    fn string(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.field(key)?.and_then(|v| v.as_str()).map(|s| s.to_owned()))
+    }
+
+    /// `[package.metadata.ipk]`, empty if there is none.
+    fn metadata(&self) -> Table {
+        self.package
+            .get("metadata")
+            .and_then(|m| m.get("ipk"))
+            .and_then(|m| m.as_table())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Where `cargo build --release` puts the binaries.
+    fn release_dir(&self) -> PathBuf {
+        match &self.target {
+            Some(triple) => self.target_dir.join(triple).join("release"),
+            None => self.target_dir.join("release"),
+        }
+    }
+
+    /// A host path from `Cargo.toml`. `target/` is where cargo actually
+    /// builds and `target/release/` the release folder of the target, as
+    /// cargo-deb does it.
+    fn host_path(&self, path: &str) -> PathBuf {
+        if let Some(rest) = path.strip_prefix("target/release/") {
+            return self.release_dir().join(rest);
+        }
+        match path.strip_prefix("target/") {
+            Some(rest) => self.target_dir.join(rest),
+            None => self.dir.join(path),
+        }
+    }
+
+    fn control(&self, metadata: &Table) -> Result<String> {
+        let name = match metadata.get("name").and_then(|n| n.as_str()) {
+            Some(name) => name.to_owned(),
+            None => package_name(&self.string("name")?.context("Cargo.toml has no package.name")?),
+        };
+        let version = package_version(&self.string("version")?.unwrap_or_else(|| "0.0.0".to_owned()));
+        let Some(description) = self.string("description")? else {
+            bail!("Cargo.toml has no package.description, the control file needs one");
+        };
+        let authors: Vec<String> = match self.field("authors")? {
+            Some(Value::Array(authors)) => authors.iter().filter_map(|a| a.as_str()).map(|a| a.to_owned()).collect(),
+            _ => Vec::new(),
+        };
+        let Some(maintainer) = metadata
+            .get("maintainer")
+            .and_then(|m| m.as_str())
+            .map(|m| m.to_owned())
+            .or_else(|| authors.first().cloned())
+        else {
+            bail!("Cargo.toml has neither package.authors nor package.metadata.ipk.maintainer");
+        };
+        let arch = match (metadata.get("arch").and_then(|a| a.as_str()), &self.target) {
+            (Some(arch), _) => arch,
+            (None, Some(triple)) => triple_arch(triple)?,
+            (None, None) => env::consts::ARCH,
+        };
+
+        let mut control = format!(
+            "Package: {}\nVersion: {}\nArchitecture: {}\nMaintainer: {}\n",
+            name, version, arch, maintainer
+        );
+        for (field, value) in [
+            ("License", self.string("license")?),
+            ("Homepage", self.string("homepage")?),
+            ("Section", metadata.get("section").and_then(|s| s.as_str()).map(|s| s.to_owned())),
+            ("Priority", metadata.get("priority").and_then(|s| s.as_str()).map(|s| s.to_owned())),
+        ] {
+            if let Some(value) = value {
+                control.push_str(&format!("{}: {}\n", field, value));
+            }
+        }
+        match metadata.get("depends") {
+            Some(Value::String(depends)) => control.push_str(&format!("Depends: {}\n", depends)),
+            Some(Value::Array(depends)) => {
+                let depends: Vec<&str> = depends.iter().filter_map(|d| d.as_str()).collect();
+                control.push_str(&format!("Depends: {}\n", depends.join(", ")));
+            }
+            Some(_) => bail!("package.metadata.ipk.depends must be a string or a list"),
+            None => {}
+        }
+        // the synopsis is the first line, the rest is the extended description
+        let mut lines = description.trim().lines();
+        control.push_str(&format!("Description: {}\n", lines.next().unwrap_or_default()));
+        for line in lines {
+            let line = line.trim();
+            control.push_str(&format!(" {}\n", if line.is_empty() { "." } else { line }));
+        }
+        Ok(control)
+    }
+
+    /// Install manifest lines for the assets, or for the release build of
+    /// every binary if there are none.
+    fn manifest(&self, metadata: &Table) -> Result<String> {
+        let mut manifest = String::new();
+        let Some(assets) = metadata.get("assets") else {
+            if self.bins.is_empty() {
+                bail!("The package has no binaries, list what to install in package.metadata.ipk.assets");
+            }
+            for bin in &self.bins {
+                manifest.push_str(&format!(
+                    "{} -> /usr/bin/{} mode=0755\n",
+                    self.release_dir().join(bin).display(),
+                    bin
+                ));
+            }
+            return Ok(manifest);
+        };
+        let Some(assets) = assets.as_array() else {
+            bail!("package.metadata.ipk.assets must be a list of [source, target, mode]");
+        };
+        for asset in assets {
+            let asset: Vec<&str> = asset
+                .as_array()
+                .map(|a| a.iter().filter_map(|v| v.as_str()).collect())
+                .unwrap_or_default();
+            let [source, target, rest @ ..] = &asset[..] else {
+                bail!("Asset {:?} must be [source, target, mode]", asset);
+            };
+            manifest.push_str(&format!("{} -> {}", self.host_path(source).display(), target));
+            if let Some(mode) = rest.first() {
+                manifest.push_str(&format!(" mode=0{}", mode.trim_start_matches('0')));
+            }
+            manifest.push('\n');
+        }
+        Ok(manifest)
+    }
+
+    /// Settings building the package this project describes into
+    /// `target/ipk`.
+    pub fn builder(&self) -> Result<IpkBuilder> {
+        let metadata = self.metadata();
+        let mut data = IpkBuilder::default();
+        data.control_file.file_or_text = ScriptSource::FromTextfield;
+        data.control_file.from_textbox = self.control(&metadata)?;
+        data.manifest.enabled = true;
+        data.manifest.file_or_text = ScriptSource::FromTextfield;
+        data.manifest.from_textbox = self.manifest(&metadata)?;
+        for (name, script) in [
+            ("postinst", &mut data.postinst),
+            ("preinst", &mut data.preinst),
+            ("prerm", &mut data.prerm),
+        ] {
+            if let Some(path) = metadata.get(name).and_then(|p| p.as_str()) {
+                script.enabled = true;
+                script.file_or_text = ScriptSource::FromPath;
+                script.picked_path = Some(self.dir.join(path));
+            }
+        }
+        data.output_path = Some(self.target_dir.join("ipk").display().to_string());
+        data.output_name = OUTPUT_NAME.to_owned();
+        Ok(data)
+    }
+
+    /// Run `cargo build --release` for the project and its target.
+    pub fn build(&self) -> Result<()> {
+        let cargo = env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+        let mut command = Command::new(cargo);
+        command.args(["build", "--release"]);
+        if let Some(triple) = &self.target {
+            command.args(["--target", triple]);
+        }
+        info!("Running {:?} in {}", command, self.dir.display());
+        let status = command
+            .current_dir(&self.dir)
+            .status()
+            .context("Could not run cargo")?;
+        if !status.success() {
+            bail!("cargo build failed with {}", status);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_cargo_names_and_versions() {
+        assert_eq!(package_name("my_daemon"), "my-daemon");
+        assert_eq!(package_version("1.0.0-rc.1"), "1.0.0~rc.1");
+        assert_eq!(package_version("1.0.0+build.5"), "1.0.0+build.5");
+    }
+
+    #[test]
+    fn arch_comes_from_the_triple() {
+        assert_eq!(triple_arch("armv7-unknown-linux-gnueabihf").unwrap(), "armv7");
+        assert_eq!(triple_arch("aarch64-unknown-linux-musl").unwrap(), "aarch64");
+        assert!(triple_arch("armv7").is_err());
+    }
+}
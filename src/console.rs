@@ -0,0 +1,301 @@
+use eframe::{
+    egui::{self, RichText},
+    epaint::Color32,
+};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::SystemTime,
+};
+
+/// Oldest entries are dropped beyond this.
+const MAX_ENTRIES: usize = 10_000;
+
+pub enum Entry {
+    Record {
+        time: SystemTime,
+        level: Level,
+        target: String,
+        message: String,
+    },
+    /// start of a build
+    Separator { time: SystemTime, title: String },
+}
+
+static ENTRIES: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+
+/// Level the console keeps records down to, off until [`set_level`].
+static CONSOLE_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Off as usize);
+/// Level `RUST_LOG` asks for on stderr.
+static ENV_LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Off as usize);
+
+fn level_filter(level: &AtomicUsize) -> LevelFilter {
+    LevelFilter::iter()
+        .nth(level.load(Ordering::Relaxed))
+        .unwrap_or(LevelFilter::Trace)
+}
+
+fn entries() -> MutexGuard<'static, Vec<Entry>> {
+    ENTRIES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn push(entry: Entry) {
+    let mut entries = entries();
+    if entries.len() >= MAX_ENTRIES {
+        entries.drain(..MAX_ENTRIES / 10);
+    }
+    entries.push(entry);
+}
+
+/// Keeps records for the log console and passes them on to env_logger.
+struct Logger {
+    env: env_logger::Logger,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= level_filter(&CONSOLE_LEVEL) || self.env.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.env.matches(record) {
+            self.env.log(record);
+        }
+        // dependencies are chatty at debug level
+        let ours = record.target().starts_with(env!("CARGO_CRATE_NAME"));
+        if record.level() <= level_filter(&CONSOLE_LEVEL) && (ours || record.level() <= Level::Info) {
+            push(Entry::Record {
+                time: SystemTime::now(),
+                level: record.level(),
+                target: record.target().to_owned(),
+                message: record.args().to_string(),
+            });
+        }
+    }
+
+    fn flush(&self) {
+        self.env.flush();
+    }
+}
+
+/// Install the logger. `RUST_LOG` controls what goes to stderr; the console
+/// keeps nothing until [`set_level`] is called.
+pub fn init() {
+    let env = env_logger::Builder::from_default_env().build();
+    ENV_LEVEL.store(env.filter() as usize, Ordering::Relaxed);
+    if log::set_boxed_logger(Box::new(Logger { env })).is_ok() {
+        log::set_max_level(env_max_level());
+    }
+}
+
+fn env_max_level() -> LevelFilter {
+    level_filter(&ENV_LEVEL).max(level_filter(&CONSOLE_LEVEL))
+}
+
+/// Keep records down to `level` in the console, debug records only of this
+/// crate. Nothing more is logged than the console or `RUST_LOG` asks for.
+pub fn set_level(level: LevelFilter) {
+    CONSOLE_LEVEL.store(level as usize, Ordering::Relaxed);
+    log::set_max_level(env_max_level());
+}
+
+/// Mark the start of a build in the console.
+pub fn separator(title: impl Into<String>) {
+    push(Entry::Separator {
+        time: SystemTime::now(),
+        title: title.into(),
+    });
+}
+
+fn clock(time: SystemTime) -> String {
+    // `2024-01-31T12:34:56Z` -> `12:34:56`
+    let stamp = humantime::format_rfc3339_seconds(time).to_string();
+    stamp.get(11..19).unwrap_or(&stamp).to_owned()
+}
+
+fn level_color(level: Level) -> Color32 {
+    match level {
+        Level::Error => Color32::RED,
+        Level::Warn => Color32::from_rgb(230, 150, 0),
+        Level::Info => Color32::GRAY,
+        Level::Debug | Level::Trace => Color32::DARK_GRAY,
+    }
+}
+
+/// One line of the console panel, copied out of the entries so they are not
+/// locked while it is drawn.
+#[derive(Debug, PartialEq)]
+enum Line {
+    Record {
+        clock: String,
+        level: Level,
+        target: String,
+        message: String,
+    },
+    Separator {
+        clock: String,
+        title: String,
+    },
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Line::Record { clock, level, target, message } => {
+                write!(f, "{} {:<5} {}: {}", clock, level, target, message)
+            }
+            Line::Separator { clock, title } => write!(f, "---- {} {} ----", clock, title),
+        }
+    }
+}
+
+/// Filter settings of the console panel.
+pub struct View {
+    pub open: bool,
+    pub level: LevelFilter,
+    pub search: String,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            open: false,
+            level: LevelFilter::Info,
+            search: String::new(),
+        }
+    }
+}
+
+impl View {
+    fn shows(&self, level: Level, message: &str) -> bool {
+        level <= self.level
+            && (self.search.is_empty()
+                || message.to_lowercase().contains(&self.search.to_lowercase()))
+    }
+
+    /// The entries that pass the filter.
+    fn lines(&self, entries: &[Entry]) -> Vec<Line> {
+        entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::Record { time, level, target, message } if self.shows(*level, message) => {
+                    Some(Line::Record {
+                        clock: clock(*time),
+                        level: *level,
+                        target: target.clone(),
+                        message: message.clone(),
+                    })
+                }
+                Entry::Separator { time, title } => Some(Line::Separator {
+                    clock: clock(*time),
+                    title: title.clone(),
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        let lines = self.lines(&entries());
+        ui.horizontal(|ui| {
+            let level = self.level;
+            egui::ComboBox::from_id_source("log_level")
+                .selected_text(self.level.to_string())
+                .show_ui(ui, |ui| {
+                    for level in [LevelFilter::Error, LevelFilter::Warn, LevelFilter::Info, LevelFilter::Debug] {
+                        ui.selectable_value(&mut self.level, level, level.to_string());
+                    }
+                });
+            if self.level != level {
+                set_level(self.level);
+            }
+            ui.add(egui::TextEdit::singleline(&mut self.search).hint_text("search"));
+            if ui.button("Copy").clicked() {
+                let text: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+                ui.output_mut(|o| o.copied_text = text);
+            }
+            if ui.button("Clear").clicked() {
+                entries().clear();
+            }
+        });
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        egui::ScrollArea::both()
+            .auto_shrink([false, false])
+            .stick_to_bottom(true)
+            .show_rows(ui, row_height, lines.len(), |ui, rows| {
+                for line in &lines[rows] {
+                    match line {
+                        Line::Record { clock, level, message, .. } => {
+                            ui.horizontal(|ui| {
+                                ui.add(egui::Label::new(RichText::new(clock).monospace().weak()).wrap(false));
+                                ui.add(
+                                    egui::Label::new(
+                                        RichText::new(format!("{:<5}", level)).monospace().color(level_color(*level)),
+                                    )
+                                    .wrap(false),
+                                );
+                                ui.add(egui::Label::new(RichText::new(message).monospace()).wrap(false));
+                            });
+                        }
+                        Line::Separator { .. } => {
+                            ui.add(egui::Label::new(RichText::new(line.to_string()).monospace().strong()).wrap(false));
+                        }
+                    }
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn record(level: Level, message: &str) -> Entry {
+        Entry::Record {
+            time: SystemTime::UNIX_EPOCH + Duration::from_secs(45_296),
+            level,
+            target: "ipkbuilder".to_owned(),
+            message: message.to_owned(),
+        }
+    }
+
+    #[test]
+    fn filters_by_level_and_search() {
+        let entries = [
+            record(Level::Debug, "walking data"),
+            Entry::Separator {
+                time: SystemTime::UNIX_EPOCH,
+                title: "Build app".to_owned(),
+            },
+            record(Level::Warn, "Unknown variable ${X}"),
+            record(Level::Info, "Wrote package"),
+        ];
+        let mut view = View::default();
+        let text: Vec<String> = view.lines(&entries).iter().map(|l| l.to_string()).collect();
+        assert_eq!(
+            text,
+            [
+                "---- 00:00:00 Build app ----",
+                "12:34:56 WARN  ipkbuilder: Unknown variable ${X}",
+                "12:34:56 INFO  ipkbuilder: Wrote package",
+            ]
+        );
+        view.search = "VARIABLE".to_owned();
+        view.level = LevelFilter::Debug;
+        assert_eq!(view.lines(&entries).len(), 2);
+        view.search = "walking".to_owned();
+        assert!(matches!(view.lines(&entries)[0], Line::Record { level: Level::Debug, .. }));
+    }
+
+    #[test]
+    fn levels_round_trip() {
+        for level in LevelFilter::iter() {
+            assert_eq!(level_filter(&AtomicUsize::new(level as usize)), level);
+        }
+    }
+}
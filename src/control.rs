@@ -0,0 +1,220 @@
+use std::fmt;
+
+/// Fields every binary package needs.
+pub const REQUIRED_FIELDS: &[&str] = &["Package", "Version", "Architecture", "Maintainer", "Description"];
+
+/// The [`REQUIRED_FIELDS`] opkg itself refuses to install a package without.
+pub const OPKG_FIELDS: &[&str] = &["Package", "Version", "Architecture"];
+
+/// Fields holding a list of package relationships.
+pub const RELATIONSHIP_FIELDS: &[&str] = &[
+    "Depends",
+    "Pre-Depends",
+    "Recommends",
+    "Suggests",
+    "Provides",
+    "Conflicts",
+    "Replaces",
+    "Breaks",
+];
+
+pub const PRIORITIES: &[&str] = &["required", "important", "standard", "optional", "extra"];
+
+/// A control file as an ordered list of fields. Continuation lines of
+/// multiline fields (e.g. `Description`) are kept in the field value.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct ControlFile {
+    pub fields: Vec<(String, String)>,
+}
+
+impl ControlFile {
+    pub fn parse(text: &str) -> Self {
+        let mut fields: Vec<(String, String)> = Vec::new();
+        for line in text.lines() {
+            if line.starts_with([' ', '\t']) {
+                if let Some((_, value)) = fields.last_mut() {
+                    value.push('\n');
+                    value.push_str(line);
+                }
+            } else if let Some((key, value)) = line.split_once(':') {
+                fields.push((key.trim().to_owned(), value.trim().to_owned()));
+            }
+        }
+        Self { fields }
+    }
+
+    /// Field names are case-insensitive.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Replace the value of `key`, or append the field if it is not set yet.
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.fields.iter_mut().find(|(k, _)| k.eq_ignore_ascii_case(key)) {
+            Some((_, v)) => *v = value.to_owned(),
+            None => self.fields.push((key.to_owned(), value.to_owned())),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.fields.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+    }
+
+    /// Set every field of `other` on top of `self`. Fields left empty in
+    /// `other` keep the value they have in `self`.
+    pub fn merge(&mut self, other: &ControlFile) {
+        for (key, value) in &other.fields {
+            if !value.trim().is_empty() {
+                self.set(key, value);
+            }
+        }
+    }
+}
+
+impl ControlFile {
+    /// Problems with the fields, as `(field, message)`.
+    pub fn problems(&self) -> Vec<(String, String)> {
+        let mut problems: Vec<(String, String)> = REQUIRED_FIELDS
+            .iter()
+            .filter(|key| self.get(key).is_none_or(|v| v.trim().is_empty()))
+            .map(|key| (key.to_string(), "Required field is missing".to_owned()))
+            .collect();
+        for (key, value) in &self.fields {
+            if let Err(e) = check_field(key, value) {
+                problems.push((key.clone(), e));
+            }
+        }
+        problems
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    name.len() >= 2
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-._".contains(c))
+}
+
+fn check_version(version: &str) -> Result<(), String> {
+    if !version.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(format!("Version {} must start with a digit", version));
+    }
+    match version.chars().find(|c| !(c.is_ascii_alphanumeric() || ".+~-:".contains(*c))) {
+        Some(c) => Err(format!("Version {} contains {:?}", version, c)),
+        None => Ok(()),
+    }
+}
+
+/// One alternative of a relationship, e.g. `libc (>= 1.2)`.
+fn check_relation(relation: &str) -> Result<(), String> {
+    let (name, version) = match relation.split_once('(') {
+        Some((name, rest)) => {
+            let Some(version) = rest.trim().strip_suffix(')') else {
+                return Err(format!("Missing `)` in {}", relation));
+            };
+            (name.trim(), Some(version.trim()))
+        }
+        None => (relation.trim(), None),
+    };
+    // architecture qualifier as in `python3:any`
+    let name = name.split_once(':').map_or(name, |(name, _)| name);
+    if !is_name(name) {
+        return Err(format!("Invalid package name {:?}", name));
+    }
+    if let Some(version) = version {
+        let version = ["<<", "<=", ">=", ">>", "=", "<", ">"]
+            .iter()
+            .find_map(|op| version.strip_prefix(op))
+            .ok_or(format!("Expected an operator in ({})", version))?;
+        check_version(version.trim())?;
+    }
+    Ok(())
+}
+
+/// Check the value of a single field. Empty values are left to
+/// [`ControlFile::problems`], and values using `${...}` variables are only
+/// known at build time, so neither is checked here.
+pub fn check_field(key: &str, value: &str) -> Result<(), String> {
+    if key.is_empty() || key.starts_with('#') || key.contains(char::is_whitespace) {
+        return Err(format!("Invalid field name {:?}", key));
+    }
+    let value = value.trim();
+    if value.is_empty() || value.contains("${") {
+        return Ok(());
+    }
+    let key = key.to_ascii_lowercase();
+    match key.as_str() {
+        "package" if !is_name(value) => Err(
+            "Use lowercase letters, digits and + - . _, at least two characters".to_owned(),
+        ),
+        "version" => check_version(value),
+        "architecture" if value.contains(char::is_whitespace) => {
+            Err("Architecture must be a single word".to_owned())
+        }
+        "maintainer" if !value.contains('@') => {
+            Err("Expected a mail address, e.g. Name <user@domain.tld>".to_owned())
+        }
+        "priority" if !PRIORITIES.contains(&value) => {
+            Err(format!("Expected one of {}", PRIORITIES.join(", ")))
+        }
+        "homepage" if !(value.starts_with("http://") || value.starts_with("https://")) => {
+            Err("Expected an http:// or https:// URL".to_owned())
+        }
+        "description" if value.lines().next().unwrap_or_default().trim().is_empty() => {
+            Err("The first line is the short description and must not be empty".to_owned())
+        }
+        _ if RELATIONSHIP_FIELDS.iter().any(|f| f.eq_ignore_ascii_case(&key)) => value
+            .split(',')
+            .flat_map(|r| r.split('|'))
+            .filter(|r| !r.trim().is_empty())
+            .try_for_each(check_relation),
+        _ => Ok(()),
+    }
+}
+
+impl fmt::Display for ControlFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in &self.fields {
+            writeln!(f, "{}: {}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_writes_back() {
+        let text = "Package: app\nDescription: The app\n It does things.\n .\n More.\nDepends: libc\n";
+        let fields = ControlFile::parse(text);
+        assert_eq!(fields.get("description"), Some("The app\n It does things.\n .\n More."));
+        assert_eq!(fields.to_string(), text);
+    }
+
+    #[test]
+    fn reports_problems() {
+        let fields = ControlFile::parse(
+            "Package: App\nVersion: v1\nArchitecture: all\nMaintainer: Me <me@example.com>\nDepends: libc (>= 1.2\n",
+        );
+        let problems: Vec<String> = fields.problems().into_iter().map(|(field, _)| field).collect();
+        assert_eq!(problems, ["Description", "Package", "Version", "Depends"]);
+        let fields = ControlFile::parse(
+            "Package: app\nVersion: 1:1.0-r1\nArchitecture: all\nMaintainer: Me <me@example.com>\nDescription: x\nDepends: libc (>= 1.2) | musl\n",
+        );
+        assert!(fields.problems().is_empty(), "{:?}", fields.problems());
+    }
+
+    #[test]
+    fn merge_keeps_fields_left_empty() {
+        let mut fields = ControlFile::parse("Package: app\nDescription: The app\n");
+        fields.merge(&ControlFile::parse("Package: app-dev\nDescription: \n"));
+        assert_eq!(fields.get("Package"), Some("app-dev"));
+        assert_eq!(fields.get("Description"), Some("The app"));
+    }
+}
@@ -0,0 +1,97 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use log::info;
+use std::{io::Read, path::Path};
+use tar::Archive;
+
+use crate::{
+    archive::{self, Format},
+    control::ControlFile,
+    feed::member_name,
+};
+
+/// Debian architecture names and what opkg feeds usually call them.
+pub const ARCHITECTURES: &[(&str, &str)] = &[
+    ("amd64", "x86_64"),
+    ("arm64", "aarch64"),
+    ("i386", "i386"),
+    ("all", "all"),
+];
+
+/// How the control file changes on the way.
+#[derive(Default)]
+pub struct Options {
+    /// `(from, to)` architecture names, tried before [`ARCHITECTURES`]
+    pub architectures: Vec<(String, String)>,
+    /// fields left out of the converted control file
+    pub drop_fields: Vec<String>,
+}
+
+impl Options {
+    /// The architecture of a package converted to `to`. Names without a
+    /// mapping are kept.
+    fn architecture(&self, arch: &str, to: Format) -> String {
+        if let Some((_, mapped)) = self.architectures.iter().find(|(from, _)| from == arch) {
+            return mapped.clone();
+        }
+        let default = ARCHITECTURES.iter().find_map(|&(deb, ipk)| match to {
+            Format::Ipk if deb == arch => Some(ipk),
+            Format::Deb if ipk == arch => Some(deb),
+            _ => None,
+        });
+        default.unwrap_or(arch).to_owned()
+    }
+
+    fn apply(&self, control: &str, to: Format) -> String {
+        let mut fields = ControlFile::parse(control);
+        for field in &self.drop_fields {
+            fields.remove(field);
+        }
+        if let Some(arch) = fields.get("Architecture") {
+            let arch = self.architecture(arch, to);
+            fields.set("Architecture", &arch);
+        }
+        fields.to_string()
+    }
+}
+
+/// control.tar.gz with the control file passed through `f` and every other
+/// member kept.
+fn rewrite_control(control_tar: &[u8], f: impl Fn(&str) -> String) -> Result<Vec<u8>> {
+    let mut archive = Archive::new(GzDecoder::new(control_tar));
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut found = false;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let mut header = entry.header().clone();
+        let path = entry.path()?.into_owned();
+        let mut content = Vec::new();
+        entry.read_to_end(&mut content)?;
+        if member_name(&entry)? == "control" {
+            let control = String::from_utf8(content).context("Control file is not UTF-8")?;
+            content = f(&control).into_bytes();
+            header.set_size(content.len() as u64);
+            header.set_cksum();
+            found = true;
+        }
+        builder.append_data(&mut header, path, &content[..])?;
+    }
+    if !found {
+        bail!("control.tar has no control file");
+    }
+    archive::gzip(&builder.into_inner()?)
+}
+
+/// Convert the package at `input`, an ipk or a deb, to the format the
+/// extension of `output` names.
+pub fn convert(input: &Path, output: &Path, options: &Options) -> Result<()> {
+    let Some(to) = Format::of(output) else {
+        bail!("{} must end in .ipk or .deb", output.display());
+    };
+    let mut members = archive::read(input)?;
+    members.control = rewrite_control(&members.control, |control| options.apply(control, to))
+        .context(format!("Error converting control file of {}", input.display()))?;
+    archive::write(output, to, members.to_write())?;
+    info!("Converted {} to {}", input.display(), output.display());
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use anyhow::{Context, Result};
+use globset::Glob;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::tree::{self, Overrides};
+
+/// Exclude file read from the top of the data root. It is never packaged.
+pub const IGNORE_FILE: &str = ".ipkignore";
+
+/// Patterns shown for a new project.
+pub const DEFAULT_PATTERNS: &str = ".git/
+*.swp
+*~
+";
+
+/// One entry of the data root, relative to it.
+pub struct DataEntry {
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
+
+/// The data root split into what goes into the package and what doesn't.
+/// Entries are in walk order, so every directory comes before its content.
+/// Below an excluded directory only the directory itself is listed, unless
+/// an override includes something below it.
+#[derive(Default)]
+pub struct DataTree {
+    pub included: Vec<DataEntry>,
+    pub excluded: Vec<DataEntry>,
+}
+
+impl DataTree {
+    pub fn files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.included.iter().filter(|e| !e.is_dir).map(|e| &e.path)
+    }
+}
+
+/// Gitignore-style matcher from the project patterns and `.ipkignore`.
+pub fn matcher(root: &Path, patterns: &str) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    builder.add_line(None, IGNORE_FILE)?;
+    for line in patterns.lines() {
+        // the builder takes any line, the glob syntax is only checked here
+        let pattern = line.trim();
+        if !pattern.is_empty() && !pattern.starts_with('#') {
+            Glob::new(pattern).context(format!("Invalid exclude pattern {}", line))?;
+        }
+        builder
+            .add_line(None, line)
+            .context(format!("Invalid exclude pattern {}", line))?;
+    }
+    let ignore_file = root.join(IGNORE_FILE);
+    if ignore_file.is_file() {
+        if let Some(e) = builder.add(&ignore_file) {
+            return Err(e).context(format!("Could not read {}", ignore_file.display()));
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// True if an override includes an entry below the directory `key`.
+fn includes_below(overrides: &Overrides, key: &str) -> bool {
+    let prefix = format!("{}/", key);
+    overrides
+        .range(prefix.clone()..)
+        .take_while(|(k, _)| k.starts_with(&prefix))
+        .any(|(_, o)| o.include == Some(true))
+}
+
+/// Walk the data root, applying the exclude patterns. An include choice in
+/// `overrides` wins over the patterns, also below an excluded directory.
+pub fn walk<P: AsRef<Path>>(root: P, patterns: &str, overrides: &Overrides) -> Result<DataTree> {
+    let root = root.as_ref();
+    let matcher = matcher(root, patterns)?;
+    let mut tree = DataTree::default();
+    // excluded directories walked into for the overrides below them
+    let mut excluded_dirs: Vec<PathBuf> = Vec::new();
+    let mut walk = WalkDir::new(root).min_depth(1).sort_by_file_name().into_iter();
+    while let Some(entry) = walk.next() {
+        let entry = entry.context(format!("Could not walk data folder {}", root.display()))?;
+        let is_dir = entry.file_type().is_dir();
+        let data_entry = DataEntry {
+            path: entry.path().strip_prefix(root)?.to_owned(),
+            is_dir,
+        };
+        let key = tree::key(&data_entry.path);
+        let excluded = match overrides.get(&key).and_then(|o| o.include) {
+            Some(include) => !include,
+            None => {
+                excluded_dirs.iter().any(|d| data_entry.path.starts_with(d))
+                    || matcher.matched(entry.path(), is_dir).is_ignore()
+            }
+        };
+        if excluded {
+            if is_dir {
+                if includes_below(overrides, &key) {
+                    excluded_dirs.push(data_entry.path.clone());
+                } else {
+                    walk.skip_current_dir();
+                }
+            }
+            tree.excluded.push(data_entry);
+        } else {
+            tree.included.push(data_entry);
+        }
+    }
+    Ok(tree)
+}
+
+/// Dry-run listing: `+ path` for included and `- path` for excluded entries.
+pub fn listing(tree: &DataTree) -> String {
+    let mut lines: Vec<(&Path, char, bool)> = tree
+        .included
+        .iter()
+        .map(|e| (e.path.as_path(), '+', e.is_dir))
+        .chain(tree.excluded.iter().map(|e| (e.path.as_path(), '-', e.is_dir)))
+        .collect();
+    lines.sort();
+    lines
+        .into_iter()
+        .map(|(path, sign, is_dir)| {
+            format!("{} /{}{}\n", sign, path.display(), if is_dir { "/" } else { "" })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::scratch, tree::FileOverride};
+    use std::fs;
+
+    fn data_root(name: &str) -> PathBuf {
+        let root = scratch(&format!("exclude-{}", name));
+        for dir in ["usr/share/doc/app", "usr/bin"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in ["usr/share/doc/app/README", "usr/share/doc/app/LICENSE", "usr/bin/app", "usr/bin/app~"] {
+            fs::write(root.join(file), file).unwrap();
+        }
+        root
+    }
+
+    fn include(include: bool) -> FileOverride {
+        FileOverride {
+            include: Some(include),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn patterns_exclude_folders_and_files() {
+        let root = data_root("patterns");
+        let tree = walk(&root, "*~\nusr/share/doc/\n", &Overrides::new()).unwrap();
+        assert_eq!(
+            listing(&tree),
+            "+ /usr/\n+ /usr/bin/\n+ /usr/bin/app\n- /usr/bin/app~\n+ /usr/share/\n- /usr/share/doc/\n"
+        );
+    }
+
+    #[test]
+    fn ignore_file_adds_patterns_and_is_never_packaged() {
+        let root = data_root("ignore-file");
+        fs::write(root.join(IGNORE_FILE), "# docs are shipped separately\nusr/share/doc/\n").unwrap();
+        let tree = walk(&root, DEFAULT_PATTERNS, &Overrides::new()).unwrap();
+        assert_eq!(
+            listing(&tree),
+            "- /.ipkignore\n+ /usr/\n+ /usr/bin/\n+ /usr/bin/app\n- /usr/bin/app~\n+ /usr/share/\n- /usr/share/doc/\n"
+        );
+        assert!(matcher(&root, "[").is_err());
+    }
+
+    #[test]
+    fn override_includes_a_file_below_an_excluded_folder() {
+        let root = data_root("override-below");
+        let mut overrides = Overrides::new();
+        overrides.insert("usr/share/doc/app/LICENSE".to_owned(), include(true));
+        let tree = walk(&root, "usr/share/doc/\n", &overrides).unwrap();
+        let files: Vec<_> = tree.files().map(|p| tree::key(p)).collect();
+        assert_eq!(files, ["usr/bin/app", "usr/bin/app~", "usr/share/doc/app/LICENSE"]);
+        // the rest of the folder stays out
+        assert!(tree.excluded.iter().any(|e| e.path == Path::new("usr/share/doc/app/README")));
+    }
+
+    #[test]
+    fn override_excludes_a_folder() {
+        let root = data_root("override-folder");
+        let mut overrides = Overrides::new();
+        overrides.insert("usr/bin".to_owned(), include(false));
+        let tree = walk(&root, "", &overrides).unwrap();
+        assert!(tree.files().all(|p| !p.starts_with("usr/bin")));
+        assert!(!tree.excluded.iter().any(|e| e.path == Path::new("usr/bin/app")));
+    }
+}
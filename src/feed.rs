@@ -0,0 +1,89 @@
+use anyhow::{bail, Context, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use log::info;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{Read, Write},
+    path::Path,
+};
+use tar::Archive;
+
+/// Name of a tar member without a leading `./`.
+pub(crate) fn member_name<R: Read>(entry: &tar::Entry<R>) -> Result<String> {
+    let path = entry.path()?;
+    Ok(path
+        .to_string_lossy()
+        .trim_start_matches("./")
+        .to_owned())
+}
+
+/// Every member of control.tar.gz of a built package, by name.
+pub fn control_members<P: AsRef<Path>>(package: P) -> Result<BTreeMap<String, Vec<u8>>> {
+    let package = package.as_ref();
+    let file = File::open(package).context(format!("Could not open {}", package.display()))?;
+    let mut outer = Archive::new(GzDecoder::new(file));
+    for entry in outer.entries()? {
+        let entry = entry?;
+        if member_name(&entry)? != "control.tar.gz" {
+            continue;
+        }
+        let mut members = BTreeMap::new();
+        let mut control_tar = Archive::new(GzDecoder::new(entry));
+        for entry in control_tar.entries()? {
+            let mut entry = entry?;
+            let name = member_name(&entry)?;
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            members.insert(name, content);
+        }
+        return Ok(members);
+    }
+    bail!("{} has no control.tar.gz", package.display())
+}
+
+/// Read the control file out of a built package.
+pub fn package_control<P: AsRef<Path>>(package: P) -> Result<String> {
+    let package = package.as_ref();
+    let Some(control) = control_members(package)?.remove("control") else {
+        bail!("{} has no control file in control.tar.gz", package.display());
+    };
+    String::from_utf8(control).context(format!("Control file of {} is not UTF-8", package.display()))
+}
+
+/// Write `Packages` and `Packages.gz` listing every `.ipk` in `dir`, the way
+/// `opkg-make-index` does.
+pub fn write_index<P: AsRef<Path>>(dir: P) -> Result<()> {
+    let dir = dir.as_ref();
+    let mut packages: Vec<_> = fs::read_dir(dir)
+        .context(format!("Could not read feed folder {}", dir.display()))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().map(|e| e == "ipk").unwrap_or(false))
+        .collect();
+    packages.sort();
+
+    let mut index = String::new();
+    for package in packages {
+        let control = package_control(&package)?;
+        let content = fs::read(&package)?;
+        index.push_str(control.trim_end());
+        index.push('\n');
+        index.push_str(&format!(
+            "Filename: {}\nSize: {}\nSHA256sum: {:x}\n\n",
+            package.file_name().unwrap_or_default().to_string_lossy(),
+            content.len(),
+            Sha256::digest(&content)
+        ));
+    }
+
+    fs::write(dir.join("Packages"), &index).context("Could not write Packages")?;
+    let mut enc = GzEncoder::new(
+        File::create(dir.join("Packages.gz")).context("Could not create Packages.gz")?,
+        Compression::default(),
+    );
+    enc.write_all(index.as_bytes())?;
+    enc.finish()?;
+    info!("Wrote feed index {}", dir.join("Packages").display());
+    Ok(())
+}
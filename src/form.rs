@@ -0,0 +1,239 @@
+use eframe::{
+    egui::{self, RichText},
+    epaint::Color32,
+};
+
+use crate::control::{self, ControlFile, RELATIONSHIP_FIELDS, REQUIRED_FIELDS};
+
+/// Architectures offered in the form for a new project, one per line.
+pub const DEFAULT_ARCHITECTURES: &str = "all
+aarch64_generic
+arm_cortex-a7
+arm_cortex-a9
+mips_24kc
+mipsel_24kc
+x86_64
+";
+
+const TEXT_FIELDS: &[&str] = &["Package", "Version", "Maintainer", "Section", "Homepage", "License"];
+
+/// Fields that have their own input in the form. Everything else goes to
+/// the custom field grid.
+fn is_form_field(key: &str) -> bool {
+    TEXT_FIELDS
+        .iter()
+        .chain(RELATIONSHIP_FIELDS)
+        .chain(&["Architecture", "Priority", "Description"])
+        .any(|f| f.eq_ignore_ascii_case(key))
+}
+
+fn set_or_remove(control: &mut ControlFile, key: &str, value: &str) {
+    if value.trim().is_empty() {
+        control.remove(key);
+    } else {
+        control.set(key, value);
+    }
+}
+
+fn error_label(ui: &mut egui::Ui, control: &ControlFile, key: &str) {
+    let value = control.get(key);
+    let error = match value {
+        None | Some("") if REQUIRED_FIELDS.contains(&key) => Some("Required field is missing".to_owned()),
+        Some(value) => control::check_field(key, value).err(),
+        None => None,
+    };
+    if let Some(error) = error {
+        ui.colored_label(Color32::RED, error);
+    }
+}
+
+fn text_row(ui: &mut egui::Ui, control: &mut ControlFile, key: &str, changed: &mut bool) {
+    ui.label(key);
+    ui.vertical(|ui| {
+        let mut value = control.get(key).unwrap_or_default().to_owned();
+        if ui.text_edit_singleline(&mut value).changed() {
+            set_or_remove(control, key, &value);
+            *changed = true;
+        }
+        error_label(ui, control, key);
+    });
+    ui.end_row();
+}
+
+fn choice_row(
+    ui: &mut egui::Ui,
+    control: &mut ControlFile,
+    key: &str,
+    choices: &[&str],
+    changed: &mut bool,
+) {
+    ui.label(key);
+    ui.vertical(|ui| {
+        let current = control.get(key).unwrap_or_default().to_owned();
+        let mut picked = current.clone();
+        egui::ComboBox::from_id_source(key)
+            .selected_text(&current)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut picked, String::new(), "(not set)");
+                // keep a value from the text view selectable even if it is not in the list
+                if !current.is_empty() && !choices.contains(&current.as_str()) {
+                    ui.selectable_value(&mut picked, current.clone(), &current);
+                }
+                for choice in choices {
+                    ui.selectable_value(&mut picked, choice.to_string(), *choice);
+                }
+            });
+        if picked != current {
+            set_or_remove(control, key, &picked);
+            *changed = true;
+        }
+        error_label(ui, control, key);
+    });
+    ui.end_row();
+}
+
+/// Split a `Description` value into the synopsis and the extended
+/// description, with the control file line prefixes removed.
+fn split_description(value: &str) -> (String, String) {
+    let mut lines = value.lines();
+    let synopsis = lines.next().unwrap_or_default().trim().to_owned();
+    let extended: Vec<&str> = lines
+        .map(|line| {
+            let line = line.strip_prefix([' ', '\t']).unwrap_or(line);
+            if line == "." {
+                ""
+            } else {
+                line
+            }
+        })
+        .collect();
+    (synopsis, extended.join("\n"))
+}
+
+fn join_description(synopsis: &str, extended: &str) -> String {
+    let mut value = synopsis.trim().to_owned();
+    for line in extended.lines() {
+        value.push_str("\n ");
+        value.push_str(if line.trim().is_empty() { "." } else { line });
+    }
+    value
+}
+
+fn description_row(ui: &mut egui::Ui, control: &mut ControlFile, changed: &mut bool) {
+    ui.label("Description");
+    ui.vertical(|ui| {
+        let (mut synopsis, mut extended) = split_description(control.get("Description").unwrap_or_default());
+        let synopsis_changed = ui
+            .add(egui::TextEdit::singleline(&mut synopsis).hint_text("short description"))
+            .changed();
+        let extended_changed = ui
+            .add(
+                egui::TextEdit::multiline(&mut extended)
+                    .hint_text("long description")
+                    .desired_rows(3),
+            )
+            .changed();
+        if synopsis_changed || extended_changed {
+            set_or_remove(control, "Description", &join_description(&synopsis, &extended));
+            *changed = true;
+        }
+        error_label(ui, control, "Description");
+    });
+    ui.end_row();
+}
+
+/// Grid of the fields without an input of their own, usually `X-` fields.
+fn custom_fields(ui: &mut egui::Ui, control: &mut ControlFile, changed: &mut bool) {
+    let mut removed = None;
+    egui::Grid::new("custom_fields").num_columns(3).show(ui, |ui| {
+        for (i, (key, value)) in control.fields.iter_mut().enumerate() {
+            if is_form_field(key) {
+                continue;
+            }
+            ui.vertical(|ui| {
+                *changed |= ui
+                    .add(egui::TextEdit::singleline(key).desired_width(120.))
+                    .changed();
+                if let Err(e) = control::check_field(key, value) {
+                    ui.colored_label(Color32::RED, e);
+                }
+            });
+            *changed |= ui.text_edit_singleline(value).changed();
+            if ui.button("Remove").clicked() {
+                removed = Some(i);
+            }
+            ui.end_row();
+        }
+    });
+    if let Some(i) = removed {
+        control.fields.remove(i);
+        *changed = true;
+    }
+    if ui.button("Add field").clicked() {
+        control.fields.push(("X-".to_owned(), String::new()));
+        *changed = true;
+    }
+}
+
+/// Edit the control file `text` through a form. The text is parsed again on
+/// every frame and only rewritten when a field changes, so the form and the
+/// raw text view always show the same content.
+pub fn show(ui: &mut egui::Ui, text: &mut String, architectures: &mut String) {
+    let mut control = ControlFile::parse(text);
+    let mut changed = false;
+    let arch_choices: Vec<&str> = architectures.split_whitespace().collect();
+    egui::Grid::new("control_form").num_columns(2).striped(true).show(ui, |ui| {
+        for key in &TEXT_FIELDS[..2] {
+            text_row(ui, &mut control, key, &mut changed);
+        }
+        choice_row(ui, &mut control, "Architecture", &arch_choices, &mut changed);
+        for key in &TEXT_FIELDS[2..] {
+            text_row(ui, &mut control, key, &mut changed);
+        }
+        choice_row(ui, &mut control, "Priority", control::PRIORITIES, &mut changed);
+        description_row(ui, &mut control, &mut changed);
+    });
+    egui::CollapsingHeader::new("Relationships").show(ui, |ui| {
+        egui::Grid::new("control_relationships").num_columns(2).show(ui, |ui| {
+            for key in RELATIONSHIP_FIELDS {
+                text_row(ui, &mut control, key, &mut changed);
+            }
+        });
+    });
+    egui::CollapsingHeader::new("Custom fields").show(ui, |ui| {
+        custom_fields(ui, &mut control, &mut changed);
+    });
+    egui::CollapsingHeader::new("Architecture list").show(ui, |ui| {
+        ui.label(RichText::new("one architecture per line").small());
+        ui.add(egui::TextEdit::multiline(architectures).code_editor().desired_rows(3));
+    });
+    if changed {
+        *text = control.to_string();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn description_round_trips() {
+        let value = "The app\n It does things.\n .\n More.";
+        let (synopsis, extended) = split_description(value);
+        assert_eq!(synopsis, "The app");
+        assert_eq!(extended, "It does things.\n\nMore.");
+        assert_eq!(join_description(&synopsis, &extended), value);
+        assert_eq!(join_description(" Only a synopsis ", ""), "Only a synopsis");
+    }
+
+    #[test]
+    fn custom_fields_are_the_rest() {
+        assert!(is_form_field("package"));
+        assert!(is_form_field("Pre-Depends"));
+        assert!(!is_form_field("X-Custom"));
+        let mut control = ControlFile::parse("Package: app\nSection: net\n");
+        set_or_remove(&mut control, "Section", "  ");
+        set_or_remove(&mut control, "Homepage", "https://example.com");
+        assert_eq!(control.to_string(), "Package: app\nHomepage: https://example.com\n");
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::{bail, Context, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::{path::Path, process::Command};
+
+use crate::control::ControlFile;
+
+/// Settings for taking the package version from the local git repository.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GitVersion {
+    pub enabled: bool,
+    /// add an `X-Git-Commit` field with the full commit hash
+    pub commit_field: bool,
+    /// add an `X-Git-Dirty` field telling if there were uncommitted changes
+    pub dirty_field: bool,
+    /// refuse to build from a tree with uncommitted changes
+    pub release: bool,
+}
+
+/// State of the repository a package is built from.
+pub struct GitInfo {
+    pub commit: String,
+    pub dirty: bool,
+    pub version: String,
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Could not run git")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed in {}: {}",
+            args.join(" "),
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// `git rev-parse HEAD` of the repository `dir` is in.
+pub fn commit(dir: &Path) -> Option<String> {
+    git(dir, &["rev-parse", "HEAD"]).ok()
+}
+
+/// Turn `git describe --tags --long` output into a package version:
+/// `v1.2.3-0-gabc1234` -> `1.2.3`, `v1.2.3-5-gabc1234` -> `1.2.3+git5.abc1234`.
+/// Whatever comes before the first digit of the tag (`v`, `release-`) is
+/// dropped; tags without a digit give no version.
+fn version_from_describe(describe: &str) -> Option<String> {
+    let (rest, hash) = describe.rsplit_once("-g")?;
+    let (tag, count) = rest.rsplit_once('-')?;
+    let tag = tag.trim_start_matches(|c: char| !c.is_ascii_digit());
+    if tag.is_empty() || count.parse::<u64>().is_err() {
+        return None;
+    }
+    Some(match count {
+        "0" => tag.to_owned(),
+        count => format!("{}+git{}.{}", tag, count, hash),
+    })
+}
+
+/// Read the repository `dir` is in. Only local git commands are run.
+pub fn describe(dir: &Path) -> Result<GitInfo> {
+    let commit = git(dir, &["rev-parse", "HEAD"])?;
+    let dirty = !git(dir, &["status", "--porcelain", "--untracked-files=no"])?.is_empty();
+    let version = match git(dir, &["describe", "--tags", "--long", "--abbrev=7"]) {
+        Ok(describe) => version_from_describe(&describe)
+            .context(format!("Could not derive a version from {}, the tag needs a version number", describe))?,
+        // no tags yet
+        Err(_) => format!(
+            "0.0.0+git{}.{}",
+            git(dir, &["rev-list", "--count", "HEAD"])?,
+            &commit[..7.min(commit.len())]
+        ),
+    };
+    Ok(GitInfo {
+        commit,
+        dirty,
+        version,
+    })
+}
+
+/// Set `Version` and the optional `X-Git-*` fields of `control` from the
+/// repository `dir` is in.
+pub fn apply(settings: &GitVersion, dir: &Path, control: &str) -> Result<String> {
+    let info = describe(dir)?;
+    if info.dirty && settings.release {
+        bail!(
+            "Refusing release build: {} has uncommitted changes",
+            dir.display()
+        );
+    }
+    info!("Version {} from git commit {}", info.version, info.commit);
+    let mut fields = ControlFile::parse(control);
+    fields.set("Version", &info.version);
+    if settings.commit_field {
+        fields.set("X-Git-Commit", &info.commit);
+    }
+    if settings.dirty_field {
+        fields.set("X-Git-Dirty", if info.dirty { "yes" } else { "no" });
+    }
+    Ok(fields.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::version_from_describe;
+
+    #[test]
+    fn versions_from_describe() {
+        assert_eq!(version_from_describe("v1.2.3-0-gabc1234").as_deref(), Some("1.2.3"));
+        assert_eq!(version_from_describe("1.2.3-5-gabc1234").as_deref(), Some("1.2.3+git5.abc1234"));
+        // tags with dashes of their own
+        assert_eq!(version_from_describe("v2.0-rc1-3-g0123456").as_deref(), Some("2.0-rc1+git3.0123456"));
+        assert_eq!(version_from_describe("release-1.0-0-gabc1234").as_deref(), Some("1.0"));
+    }
+
+    #[test]
+    fn rejects_tags_without_a_version() {
+        assert_eq!(version_from_describe("stable-0-gabc1234"), None);
+        assert_eq!(version_from_describe("v1.0"), None);
+        assert_eq!(version_from_describe("v1.0-x-gabc1234"), None);
+    }
+}
@@ -1,19 +1,59 @@
+pub mod archive;
+pub mod batch;
+pub mod cargo;
+pub mod console;
+pub mod control;
+pub mod convert;
+pub mod exclude;
+pub mod feed;
+pub mod form;
+pub mod git;
+pub mod manifest;
+pub mod progress;
+pub mod project;
+pub mod record;
+pub mod sbom;
+pub mod service;
+pub mod session;
+pub mod shell;
+pub mod shlibdeps;
+pub mod snippets;
+pub mod split;
+#[cfg(test)]
+mod testing;
+pub mod tree;
 pub mod ui;
+pub mod validate;
+pub mod verify;
+pub mod vars;
+pub mod watch;
 
-use anyhow::{Context, bail, Result};
+use anyhow::{Context, Result};
 use flate2::{write::GzEncoder, Compression};
-use log::info;
+use log::{info, warn};
 use std::{
+    collections::{BTreeSet, HashMap},
     fs::{File, self},
     io::Read,
     mem::size_of_val,
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 use tar::{Builder, Header};
-use ui::{IpkBuilder, ScriptSource};
+use archive::{Format, Member};
+use control::ControlFile;
+use exclude::DataTree;
+use manifest::InstallFile;
+use progress::Progress;
+use ui::{FileOrPath, IpkBuilder, ScriptSource};
+use verify::Verification;
+
+/// Content of debian-binary unless the user picked another one.
+pub const DEBIAN_BINARY: &str = "2.0\n";
 
 pub fn header_from_file(f: &mut File) -> Result<(Header, Vec<u8>)> {
-    let buffer: Vec<u8> = f.bytes().flatten().collect();
+    let mut buffer = Vec::new();
+    f.read_to_end(&mut buffer)?;
     Ok((header_from_buf(&buffer[..]), buffer))
 }
 
@@ -49,21 +89,456 @@ pub fn append_file<P: AsRef<Path>>(
         "Could not open {} to append to tar",
         src_path.as_ref().display()
     ))?;
-    let mut headbuf = header_from_file(&mut file)?;
-    arch.append_data(&mut headbuf.0, tgt_path, &headbuf.1[..])?;
+    let mut headbuf = header_from_file(&mut file)
+        .context(format!("Could not read {}", src_path.as_ref().display()))?;
+    arch.append_data(&mut headbuf.0, tgt_path, &headbuf.1[..])
+        .context(format!("Could not append {} as {}", src_path.as_ref().display(), tgt_path))?;
     Ok(())
 }
 
+/// The file picked for `what`, or an error if there is none.
+fn picked_path(file: &FileOrPath, what: &str) -> Result<PathBuf> {
+    file.picked_path
+        .clone()
+        .context(format!("No file picked for the {}", what))
+}
+
+/// The output folder, or an error if none was picked.
+pub fn output_dir(data: &IpkBuilder) -> Result<PathBuf> {
+    data.output_path
+        .as_ref()
+        .map(PathBuf::from)
+        .context("No output folder picked")
+}
+
+/// The control file as the user wrote it.
+fn control_source(data: &IpkBuilder) -> Result<String> {
+    if data.control_file.file_or_text == ScriptSource::FromPath {
+        let path = picked_path(&data.control_file, "control file")?;
+        fs::read_to_string(&path)
+            .context(format!("Could not read control file {}", path.display()))
+    } else {
+        Ok(data.control_file.from_textbox.clone())
+    }
+}
+
+/// The control file and enabled scripts with all variables expanded, for
+/// showing to the user before building. The control file is the one the
+/// build would package, git version and generated fields included.
+pub fn preview_variables(data: &IpkBuilder) -> Result<String> {
+    let base = vars::base_variables(&data.variables, git_dir(data).as_deref())?;
+    let control = control_text(data, &base)?;
+    let vars = vars::with_control_fields(base, &control);
+    let mut preview = format!("control:\n{}\n", control);
+    // there is no postrm editor, it is only generated
+    let postrm = FileOrPath::default();
+    for (name, script) in [
+        ("postinst", &data.postinst),
+        ("preinst", &data.preinst),
+        ("prerm", &data.prerm),
+        ("postrm", &postrm),
+    ] {
+        if let Some(text) = script_content(data, script, name, Some(&vars))? {
+            preview.push_str(&format!("\n{}:\n{}\n", name, String::from_utf8_lossy(&text)));
+        }
+    }
+    Ok(preview)
+}
+
+/// The control file as it goes into control.tar.gz, with generated
+/// fields already merged in. `base` are the build's
+/// [`vars::base_variables`].
+pub fn control_text(data: &IpkBuilder, base: &HashMap<String, String>) -> Result<String> {
+    let control = expanded_control(data, base)?;
+    with_shlibdeps(data, &control, None)
+}
+
+/// The control file with variables and the git version applied, before
+/// the shared library dependencies are added.
+fn expanded_control(data: &IpkBuilder, base: &HashMap<String, String>) -> Result<String> {
+    let mut control = control_source(data)?;
+    if data.variables.enabled {
+        control = vars::expand(&control, base)
+            .context("Error expanding variables in control file")?;
+    }
+    if data.git_version.enabled {
+        let dir = git_dir(data).context("No directory to read the git repository from")?;
+        control = git::apply(&data.git_version, &dir, &control)
+            .context("Error deriving version from git")?;
+    }
+    Ok(control)
+}
+
+/// `control` with the dependencies of the packaged libraries and binaries
+/// merged in, if shlibdeps is enabled. `files` selects them as in
+/// [`install_files`].
+fn with_shlibdeps(data: &IpkBuilder, control: &str, files: Option<&[PathBuf]>) -> Result<String> {
+    if !data.shlibdeps.enabled {
+        return Ok(control.to_owned());
+    }
+    info!("Resolving shared library dependencies");
+    let inputs: Vec<PathBuf> = install_files(data, files)?
+        .into_iter()
+        .filter(|f| !f.is_dir)
+        .map(|f| f.src)
+        .collect();
+    shlibdeps::apply(&data.shlibdeps, &inputs, control)
+        .context("Error generating shared library dependencies")
+}
+
+/// The directory whose git repository describes the build: the project
+/// directory if there is a project file, the data root otherwise.
+pub fn git_dir(data: &IpkBuilder) -> Option<PathBuf> {
+    match &data.project_path {
+        Some(project) => project.parent().map(|p| p.to_owned()),
+        None => data.data_path.as_ref().map(PathBuf::from),
+    }
+}
+
+/// The directory of the project file, or the working directory.
+pub fn project_dir(data: &IpkBuilder) -> PathBuf {
+    data.project_path
+        .as_ref()
+        .and_then(|p| p.parent())
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(|p| p.to_owned())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// The data root with the exclude patterns applied. Empty without a data root.
+pub fn data_tree(data: &IpkBuilder) -> Result<DataTree> {
+    match &data.data_path {
+        Some(data_path) => exclude::walk(data_path, &data.exclude, &data.overrides),
+        None => Ok(DataTree::default()),
+    }
+}
+
+/// Everything that goes into data.tar.gz: the data root with its overrides
+/// applied, then the manifest. With `selected` set, only those files
+/// (relative to the package root) and the folders leading to them are kept.
+pub fn install_files(data: &IpkBuilder, selected: Option<&[PathBuf]>) -> Result<Vec<InstallFile>> {
+    let ancestors: BTreeSet<&Path> = selected
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|f| f.ancestors().skip(1))
+        .collect();
+    let keep = |path: &Path, is_dir: bool| match selected {
+        None => true,
+        Some(_) if is_dir => ancestors.contains(path),
+        Some(selected) => selected.iter().any(|s| s == path),
+    };
+
+    let mut entries = Vec::new();
+    if let Some(data_path) = &data.data_path {
+        for entry in data_tree(data)?.included {
+            if !keep(&entry.path, entry.is_dir) {
+                continue;
+            }
+            let choice = data.overrides.get(&tree::key(&entry.path)).cloned().unwrap_or_default();
+            entries.push(InstallFile {
+                src: Path::new(data_path).join(&entry.path),
+                mode: choice
+                    .parsed_mode()
+                    .context(format!("Mode of {}", entry.path.display()))?,
+                owner: choice.parsed_owner(),
+                conffile: choice.conffile,
+                dest: entry.path,
+                is_dir: entry.is_dir,
+            });
+        }
+    }
+    entries.extend(
+        extra_files(data)?
+            .into_iter()
+            .filter(|m| keep(&m.dest, m.is_dir)),
+    );
+    Ok(entries)
+}
+
+/// Files installed from outside the data root: the manifest entries and
+/// the service unit or init script.
+pub fn extra_files(data: &IpkBuilder) -> Result<Vec<InstallFile>> {
+    let mut files = manifest_files(data)?;
+    files.extend(data.service.install_file().context("Error in service section")?);
+    Ok(files)
+}
+
+/// Files installed through the manifest, if it is enabled. Host paths are
+/// relative to the manifest file, or to the project for an inline manifest.
+pub fn manifest_files(data: &IpkBuilder) -> Result<Vec<InstallFile>> {
+    if !data.manifest.enabled {
+        return Ok(Vec::new());
+    }
+    let (text, base) = if data.manifest.file_or_text == ScriptSource::FromPath {
+        let path = picked_path(&data.manifest, "install manifest")?;
+        let text = fs::read_to_string(&path)
+            .context(format!("Could not read manifest {}", path.display()))?;
+        (text, path.parent().map(|p| p.to_owned()).unwrap_or_default())
+    } else {
+        (data.manifest.from_textbox.clone(), project_dir(data))
+    };
+    manifest::resolve(&text, &base).context("Error in install manifest")
+}
+
+/// Content of a maintainer script, with variables expanded if `vars` is set.
+pub fn script_text(
+    script: &FileOrPath,
+    name: &str,
+    vars: Option<&HashMap<String, String>>,
+) -> Result<Vec<u8>> {
+    let content = if script.file_or_text == ScriptSource::FromPath {
+        let path = picked_path(script, &format!("{} script", name))?;
+        fs::read(&path).context(format!("Could not read {} script {}", name, path.display()))?
+    } else {
+        script.from_textbox.clone().into_bytes()
+    };
+    match vars {
+        Some(vars) => {
+            let text = String::from_utf8(content)
+                .context(format!("{} script is not valid UTF-8", name))?;
+            Ok(vars::expand(&text, vars)
+                .context(format!("Error expanding variables in {} script", name))?
+                .into_bytes())
+        }
+        None => Ok(content),
+    }
+}
+
+/// A maintainer script as it goes into the package: the user script if it
+/// is enabled, with the service logic and the project's snippets for `name`
+/// added. `None` if there is none of them.
+pub fn script_content(
+    data: &IpkBuilder,
+    script: &FileOrPath,
+    name: &str,
+    vars: Option<&HashMap<String, String>>,
+) -> Result<Option<Vec<u8>>> {
+    let user_script = if script.enabled {
+        Some(script_text(script, name, vars)?)
+    } else {
+        None
+    };
+    let mut blocks = Vec::new();
+    blocks.extend(data.service.block(name).context("Error in service section")?);
+    if !data.snippets.uses.is_empty() {
+        let library = snippets::library(&data.snippets.dirs)?;
+        blocks.extend(
+            snippets::compose(name, &library, &data.snippets.uses)
+                .context(format!("Error in snippets for {} script", name))?,
+        );
+    }
+    let block = (!blocks.is_empty()).then(|| blocks.join("\n"));
+    Ok(match (block, user_script) {
+        (None, user_script) => user_script,
+        (Some(block), None) => Some(snippets::combine(&block, None).into_bytes()),
+        (Some(block), Some(user_script)) => {
+            let user_script = String::from_utf8(user_script)
+                .context(format!("{} script is not valid UTF-8", name))?;
+            Some(snippets::combine(&block, Some(&user_script)).into_bytes())
+        }
+    })
+}
+
+/// A package written by [`build_all`].
+pub struct BuiltPackage {
+    pub path: PathBuf,
+    /// the control file as packaged
+    pub control: String,
+    /// what reading the package back found
+    pub verification: Verification,
+    /// hex SHA-256 of the package file
+    pub sha256: String,
+    pub size: u64,
+}
+
 pub fn make_package(
     data: &IpkBuilder,
+    progress: &Progress,
 ) -> Result<String> {
-    let mut control_tar: PathBuf = data.output_path.clone().unwrap().into();
-    let mut data_tar:  PathBuf = data.output_path.clone().unwrap().into();
+    Ok(make_packages(data, progress)?
+        .iter()
+        .map(|p| p.path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", "))
+}
+
+/// Build every package and write the feed index if the project asks for it.
+pub fn make_packages(data: &IpkBuilder, progress: &Progress) -> Result<Vec<BuiltPackage>> {
+    let built = build_all(data, progress)?;
+    if data.index_feed {
+        feed::write_index(output_dir(data)?)
+            .context("Error writing feed index")?;
+    }
+    Ok(built)
+}
+
+/// Build the package, or every split package if the project has any.
+/// The maintainer scripts and service logic only go into the split package
+/// named like the main control file's `Package`, and each split depends on
+/// the shared libraries its own files need.
+pub fn build_all(data: &IpkBuilder, progress: &Progress) -> Result<Vec<BuiltPackage>> {
+    // computed once so every package of the build sees the same BUILD_DATE
+    let base = vars::base_variables(&data.variables, git_dir(data).as_deref())?;
+    if data.split_packages.is_empty() {
+        let control = control_text(data, &base)?;
+        let package_name = output_name(data, &base, &control)?;
+        return Ok(vec![build_package(data, &base, &control, None, true, &package_name, progress)?]);
+    }
+
+    let control = expanded_control(data, &base)?;
+    let main = ControlFile::parse(&control).get("Package").unwrap_or_default().to_owned();
+    if !data.split_packages.iter().any(|p| p.name.trim() == main) {
+        warn!("No split package is named {}, the maintainer scripts are left out", main);
+    }
+
+    let mut paths: Vec<PathBuf> = data_tree(data)?.files().cloned().collect();
+    paths.extend(
+        extra_files(data)?
+            .into_iter()
+            .filter(|m| !m.is_dir)
+            .map(|m| m.dest),
+    );
+    let assigned = split::assign(paths, &data.split_packages)?;
+    let mut built = Vec::new();
+    for (package, files) in data.split_packages.iter().zip(assigned) {
+        let mut fields = ControlFile::parse(&control);
+        fields.merge(&ControlFile::parse(&package.control));
+        fields.set("Package", &package.name);
+        let control = with_shlibdeps(data, &fields.to_string(), Some(&files))
+            .context(format!("Error building split package {}", package.name))?;
+        info!("Building split package {} with {} files", package.name, files.len());
+        let package = build_package(
+            data,
+            &base,
+            &control,
+            Some(&files),
+            package.name.trim() == main,
+            &format!("{}.ipk", package.name),
+            progress,
+        )
+        .context(format!("Error building split package {}", package.name))?;
+        built.push(package);
+    }
+    Ok(built)
+}
+
+/// File name of the package, with `${PACKAGE}`, `${VERSION}`, `${ARCH}` and
+/// the other variables expanded.
+pub fn output_name(data: &IpkBuilder, base: &HashMap<String, String>, control: &str) -> Result<String> {
+    if !data.output_name.contains('$') {
+        return Ok(data.output_name.clone());
+    }
+    let vars = vars::with_control_fields(base.clone(), control);
+    vars::expand(&data.output_name, &vars).context("Error expanding variables in package file name")
+}
+
+/// Paths of the packages a build would write, without building them.
+pub fn output_paths(data: &IpkBuilder) -> Result<Vec<PathBuf>> {
+    let output = output_dir(data)?;
+    if !data.split_packages.is_empty() {
+        return Ok(data
+            .split_packages
+            .iter()
+            .map(|p| output.join(format!("{}.ipk", p.name)))
+            .collect());
+    }
+    if !data.output_name.contains('$') {
+        return Ok(vec![output.join(&data.output_name)]);
+    }
+    let base = vars::base_variables(&data.variables, git_dir(data).as_deref())?;
+    let control = control_text(data, &base)?;
+    Ok(vec![output.join(output_name(data, &base, &control)?)])
+}
+
+/// Build one package from `control` and the data root, with its checksum
+/// file, build record and, if asked for, SBOMs next to it. With `files`
+/// set, only those paths (relative to the data root) go into data.tar.gz,
+/// and `scripts` says whether the maintainer scripts go into control.tar.gz.
+/// Everything is written under a `.partial` name first and renamed into
+/// place once the whole build succeeded, so a failed or cancelled build, or
+/// one that does not pass verification, leaves the previous package and its
+/// files as they were.
+pub fn build_package(
+    data: &IpkBuilder,
+    base: &HashMap<String, String>,
+    control: &str,
+    files: Option<&[PathBuf]>,
+    scripts: bool,
+    package_name: &str,
+    progress: &Progress,
+) -> Result<BuiltPackage> {
+    let output = output_dir(data)?;
+    // intermediate archives are named after the package so builds sharing
+    // an output folder don't clobber each other
+    let stem = package_name.trim_end_matches(".ipk");
+    let control_tar = output.join(format!("{}.control.tar.gz", stem));
+    let data_tar = output.join(format!("{}.data.tar.gz", stem));
+    let package_tar = output.join(package_name);
+    let [checksum, record] = record::paths(&package_tar);
+    let [spdx, cyclonedx] = sbom::paths(&package_tar);
+    let outputs = [&package_tar, &checksum, &record, &spdx, &cyclonedx];
+    let staged = outputs.map(|path| {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        path.with_file_name(format!("{}.partial", name))
+    });
+    let started = SystemTime::now();
+    let result = write_package(
+        data,
+        base,
+        control,
+        files,
+        scripts,
+        [&control_tar, &data_tar, &staged[0]],
+        progress,
+    )
+    .and_then(|mut built| {
+        built.path = package_tar.clone();
+        record::write(data, &built, started, [&staged[1], &staged[2]])
+            .context("Error writing checksum and build record")?;
+        if data.sbom {
+            sbom::write(&built, [&staged[3], &staged[4]]).context("Error writing SBOMs")?;
+        }
+        for (staged, path) in staged.iter().zip(outputs) {
+            if staged.exists() {
+                fs::rename(staged, path).context(format!("Could not replace {}", path.display()))?;
+            }
+        }
+        Ok(built)
+    });
+    if result.is_err() {
+        // only files of this build, the previous package is not touched
+        for path in [&control_tar, &data_tar].into_iter().chain(&staged) {
+            if fs::remove_file(path).is_ok() {
+                info!("Removed partial output {}", path.display());
+            }
+        }
+    }
+    result
+}
+
+fn write_package(
+    data: &IpkBuilder,
+    base: &HashMap<String, String>,
+    control: &str,
+    files: Option<&[PathBuf]>,
+    scripts: bool,
+    [control_tar, data_tar, package_tar]: [&PathBuf; 3],
+    progress: &Progress,
+) -> Result<BuiltPackage> {
+    let entries = install_files(data, files)?;
+    progress.start(
+        package_tar.file_name().unwrap_or_default().to_string_lossy(),
+        entries.len(),
+    );
+    let conffiles: String = entries
+        .iter()
+        .filter(|e| e.conffile)
+        .map(|e| format!("/{}\n", e.dest.display()))
+        .collect();
     {
         // do this in it's own scope so files are dropped and closed at the end of the scope
-        control_tar.push("control.tar.gz");
         let control_archive =
-            File::create(&control_tar).context("Could not create control.tar.gz")?;
+            File::create(control_tar).context(format!("Could not create {}", control_tar.display()))?;
         let enc = GzEncoder::new(&control_archive, Compression::default());
         let mut tar = tar::Builder::new(enc);
 
@@ -75,143 +550,94 @@ pub fn make_package(
                 .to_str()
                 .unwrap_or_default()
         );
-        if data.control_file.file_or_text == ScriptSource::FromPath {
-           append_file(data.control_file.picked_path.clone().unwrap(), &mut tar, "control", Some(&control_tar))?;
-        } else {
-        let mut header = header_from_buf(data.control_file.from_textbox.as_bytes());
-        tar.append_data(
-            &mut header,
-            "control",
-            data.control_file.from_textbox.as_bytes(),
-        )?;
+        let mut header = header_from_buf(control.as_bytes());
+        tar.append_data(&mut header, "control", control.as_bytes())
+            .context(format!("Could not write {}", control_tar.display()))?;
+        if !conffiles.is_empty() {
+            let mut header = header_from_buf(conffiles.as_bytes());
+            tar.append_data(&mut header, "conffiles", conffiles.as_bytes())
+                .context(format!("Could not write {}", control_tar.display()))?;
         }
 
-        if data.postinst.enabled {
-        info!(
-            "Packaging postinst script into {}",
-            control_tar
-                .file_name()
-                .unwrap_or_default()
-                .to_str()
-                .unwrap_or_default()
-        );
-        if data.postinst.file_or_text == ScriptSource::FromPath {
-           append_file(data.postinst.picked_path.clone().unwrap(), &mut tar, "postinst", Some(&control_tar))?;
+        let vars = if data.variables.enabled {
+            Some(vars::with_control_fields(base.clone(), control))
         } else {
-        let mut header = header_from_buf(data.postinst.from_textbox.as_bytes());
-        header.set_mode(0o755);
-        header.set_cksum();
-        tar.append_data(
-            &mut header,
-            "postinst",
-            data.postinst.from_textbox.as_bytes(),
-        )?;
+            None
+        };
+        if scripts {
+            // there is no postrm editor, it is only generated
+            let postrm = FileOrPath::default();
+            for (name, script) in [
+                ("postinst", &data.postinst),
+                ("preinst", &data.preinst),
+                ("prerm", &data.prerm),
+                ("postrm", &postrm),
+            ] {
+                let Some(content) = script_content(data, script, name, vars.as_ref())? else {
+                    continue;
+                };
+                info!(
+                    "Packaging {} script into {}",
+                    name,
+                    control_tar
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_str()
+                        .unwrap_or_default()
+                );
+                let mut header = header_from_buf(&content[..]);
+                header.set_mode(0o755);
+                header.set_cksum();
+                tar.append_data(&mut header, name, &content[..])
+                    .context(format!("Could not write {}", control_tar.display()))?;
+            }
         }
-        }
-
-        if data.preinst.enabled {
-        info!(
-            "Packaging preinst script into {}",
-            control_tar
-                .file_name()
-                .unwrap_or_default()
-                .to_str()
-                .unwrap_or_default()
-        );
-        if data.preinst.file_or_text == ScriptSource::FromPath {
-           append_file(data.preinst.picked_path.clone().unwrap(), &mut tar, "postinst", Some(&control_tar))?;
-        } else {
-        let mut header = header_from_buf(data.postinst.from_textbox.as_bytes());
-        header.set_mode(0o755);
-        header.set_cksum();
-        tar.append_data(
-            &mut header,
-            "preinst",
-        data.preinst.from_textbox.as_bytes(),
-        )?;
-        }
-        }
-
-        if data.prerm.enabled {
-        info!(
-            "Packaging prerm script into {}",
-            control_tar
-                .file_name()
-                .unwrap_or_default()
-                .to_str()
-                .unwrap_or_default()
-        );
-        if data.prerm.file_or_text == ScriptSource::FromPath {
-           append_file(Path::new(&data.prerm.picked_path.clone().unwrap()), &mut tar, "postinst", Some(&control_tar))?;
-        } else {
-        let mut header = header_from_buf(data.prerm.from_textbox.as_bytes());
-        header.set_mode(0o755);
-        header.set_cksum();
-        tar.append_data(
-            &mut header,
-            "prerm",
-            data.prerm.from_textbox.as_bytes(),
-        )?;
-        }
-        }
-        tar.finish()?;
+        tar.finish()
+            .context(format!("Could not write {}", control_tar.display()))?;
     }
         info!("Created control tar archive {}", control_tar.display());
 
-        data_tar.push("data.tar.gz");
-        let data_archive = File::create(&data_tar).context("Could not create data.tar.gz")?;
+    {
+        let data_archive = File::create(data_tar).context(format!("Could not create {}", data_tar.display()))?;
         let enc = GzEncoder::new(&data_archive, Compression::default());
         let mut tar = tar::Builder::new(enc);
-        tar.append_dir_all(
-            "", data.data_path.clone().unwrap(),
-        ).unwrap();
-
-        info!("Created data tar archive {}", data_tar.display());
-
-    let package_name = "outpackage.ipk".to_owned();
-    let mut package_tar: PathBuf = data.output_path.clone().unwrap().into();
-    package_tar.push(package_name);
-    // try to delete any previous package, do nothing if not found
-    if let Err(e) = fs::remove_file(&package_tar) {
-        if std::io::ErrorKind::NotFound != e.kind() {
-            bail!(
-                "Error deleting {} before creating package.",
-                package_tar.display()
-            );
-        }
+        manifest::append(&mut tar, &entries, &mut BTreeSet::new(), progress)?;
+        tar.into_inner()
+            .and_then(|enc| enc.finish())
+            .context(format!("Could not write {}", data_tar.display()))?;
     }
-    let package_archive = File::create(&package_tar).context("Could not create package archive")?;
-    let enc = GzEncoder::new(&package_archive, Compression::default());
-    let mut tar = tar::Builder::new(enc);
-    append_file(&control_tar, &mut tar, "control.tar.gz", Some(&package_tar))
-        .context("Error appending control.tar.gz to package archive")?;
-    append_file(&data_tar, &mut tar, "data.tar.gz", Some(&package_tar))
-        .context("Error appending data.tar.gz to package archive")?;
-        info!(
-            "Packaging debian_binary script into {}",
-            package_tar
-                .file_name()
-                .unwrap_or_default()
-                .to_str()
-                .unwrap_or_default()
-        );
-        if data.debian_binary.file_or_text == ScriptSource::FromPath {
-           append_file(Path::new(&data.debian_binary.picked_path.clone().unwrap()), &mut tar, "debin_binary", Some(&package_tar))?;
-        } else {
-        let mut header = header_from_buf(data.debian_binary.from_textbox.as_bytes());
-        header.set_mode(0o755);
-        header.set_cksum();
-        tar.append_data(
-            &mut header,
-            "debian_binary",
-            data.debian_binary.from_textbox.as_bytes(),
-        )?;
-        }
+        info!("Created data tar archive {}", data_tar.display());
 
-        info!("Created package {}", package_tar.display());
+    progress.check()?;
+    let debian_binary = if data.debian_binary.enabled {
+        DEBIAN_BINARY.as_bytes().to_vec()
+    } else if data.debian_binary.file_or_text == ScriptSource::FromPath {
+        let path = picked_path(&data.debian_binary, "debian-binary")?;
+        fs::read(&path).context(format!("Could not read debian-binary {}", path.display()))?
+    } else {
+        data.debian_binary.from_textbox.clone().into_bytes()
+    };
+    let members = [
+        Member::bytes(&debian_binary),
+        Member::file(control_tar)?,
+        Member::file(data_tar)?,
+    ];
+    archive::write(package_tar, Format::Ipk, members)?;
+    info!("Created package {}", package_tar.display());
     // cleanup
-    fs::remove_file(control_tar).context("Error removing contor.tar.gz")?;
-    fs::remove_file(data_tar).context("Error removing data.tar.gz")?;
+    fs::remove_file(control_tar).context(format!("Error removing {}", control_tar.display()))?;
+    fs::remove_file(data_tar).context(format!("Error removing {}", data_tar.display()))?;
 
-    Ok(package_tar.display().to_string())
+    let verification = verify::package(package_tar, control, &entries)
+        .context(format!("Verification of {} failed", package_tar.display()))?;
+    info!("{}", verification);
+    let package = File::open(package_tar).context(format!("Could not open {}", package_tar.display()))?;
+    let (sha256, size) = verify::digest(package).context(format!("Could not read {}", package_tar.display()))?;
+    Ok(BuiltPackage {
+        path: package_tar.clone(),
+        control: control.to_owned(),
+        verification,
+        sha256,
+        size,
+    })
 }
@@ -2,20 +2,278 @@ use eframe::{
         egui,
         run_native
     };
-use ipkbuilder::ui::IpkBuilder;
+use ipkbuilder::{
+    batch, console, convert, data_tree, exclude, make_package, progress::Progress, project,
+    ui::IpkBuilder,
+    validate,
+    watch::{Watcher, DEFAULT_DEBOUNCE},
+};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    thread,
+    time::Duration,
+};
 
-fn main() {
-    env_logger::init();
-    let _app: IpkBuilder = Default::default();
+const USAGE: &str = "Usage:
+    ipkbuilder                    start the GUI
+    ipkbuilder <project>          start the GUI with a project file loaded
+    ipkbuilder build <project>    build the package described by a project file
+    ipkbuilder build --release <project>
+                                  same, with the version taken from git and
+                                  refusing to build with uncommitted changes
+    ipkbuilder batch [--jobs <n>] [--json <file>] <project|folder>...
+                                  build many projects and print a summary,
+                                  --json - writes the summary as JSON to stdout
+    ipkbuilder list <project>     dry run: list the data root entries that are
+                                  included (+) and excluded (-)
+    ipkbuilder watch [--debounce <ms>] [--index] <project>
+                                  rebuild whenever an input changes, --index
+                                  also rewrites the Packages index of the
+                                  output folder
+    ipkbuilder convert [--arch <from>=<to>]... [--drop <field>]... <input> <output>
+                                  convert a .deb to an .ipk or back, the
+                                  output extension picks the format";
+
+fn build(project: &str, release: bool) -> ExitCode {
+    let result = project::load(project).and_then(|mut data| {
+        if release {
+            data.git_version.enabled = true;
+            data.git_version.release = true;
+        }
+        make_package(&data, &Progress::default())
+    });
+    match result {
+        Ok(package) => {
+            println!("{}", package);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn list(project: &str) -> ExitCode {
+    match project::load(project).and_then(|data| data_tree(&data)) {
+        Ok(tree) => {
+            print!("{}", exclude::listing(&tree));
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn batch(args: &[&str]) -> ExitCode {
+    let mut jobs = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let mut json = None;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--jobs" | "-j" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => jobs = n,
+                None => {
+                    eprintln!("--jobs needs a number");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--json" => match args.next() {
+                Some(file) => json = Some(file.to_string()),
+                None => {
+                    eprintln!("--json needs a file name");
+                    return ExitCode::FAILURE;
+                }
+            },
+            path => paths.push(PathBuf::from(path)),
+        }
+    }
+    let projects = match batch::collect_projects(&paths) {
+        Ok(projects) if !projects.is_empty() => projects,
+        Ok(_) => {
+            eprintln!("No project files given");
+            return ExitCode::FAILURE;
+        }
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(e) = batch::check_outputs(&projects) {
+        eprintln!("Error: {:?}", e);
+        return ExitCode::FAILURE;
+    }
+    let results = batch::run(&projects, jobs);
+    match json.as_deref() {
+        Some("-") => println!("{}", batch::to_json(&results).unwrap_or_default()),
+        Some(file) => {
+            print!("{}", batch::table(&results));
+            if let Err(e) = batch::to_json(&results).and_then(|j| Ok(fs::write(file, j)?)) {
+                eprintln!("Error writing {}: {:?}", file, e);
+                return ExitCode::FAILURE;
+            }
+        }
+        None => print!("{}", batch::table(&results)),
+    }
+    if results.iter().any(|r| r.error.is_some()) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn watch(args: &[&str]) -> ExitCode {
+    let mut debounce = DEFAULT_DEBOUNCE;
+    let mut index = false;
+    let mut project = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--debounce" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(ms) => debounce = Duration::from_millis(ms),
+                None => {
+                    eprintln!("--debounce needs a number of milliseconds");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--index" => index = true,
+            path => project = Some(path.to_owned()),
+        }
+    }
+    let Some(project) = project else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let mut data = match project::load(&project) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    data.index_feed |= index;
+    let mut watcher = match Watcher::new(&data, debounce) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut rebuild = true;
+    loop {
+        if rebuild {
+            // the project file is watched too, so pick up its changes
+            match project::load(&project) {
+                Ok(reloaded) => {
+                    data = reloaded;
+                    data.index_feed |= index;
+                }
+                Err(e) => eprintln!("Error: {:?}", e),
+            }
+            if !watcher.watches(&data) {
+                match Watcher::new(&data, debounce) {
+                    Ok(new) => watcher = new,
+                    Err(e) => eprintln!("Error: {:?}", e),
+                }
+            }
+            match validate::check(&data).and_then(|()| make_package(&data, &Progress::default())) {
+                Ok(package) => println!("{}", package),
+                Err(e) => eprintln!("Error: {:?}", e),
+            }
+        }
+        thread::sleep(Duration::from_millis(100));
+        rebuild = watcher.poll();
+    }
+}
+
+fn convert(args: &[&str]) -> ExitCode {
+    let mut options = convert::Options::default();
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--arch" => match args.next().and_then(|a| a.split_once('=')) {
+                Some((from, to)) => options.architectures.push((from.to_owned(), to.to_owned())),
+                None => {
+                    eprintln!("--arch needs <from>=<to>");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--drop" => match args.next() {
+                Some(field) => options.drop_fields.push(field.to_string()),
+                None => {
+                    eprintln!("--drop needs a field name");
+                    return ExitCode::FAILURE;
+                }
+            },
+            path => paths.push(PathBuf::from(path)),
+        }
+    }
+    let [input, output] = &paths[..] else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    match convert::convert(input, output, &options) {
+        Ok(()) => {
+            println!("{}", output.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    console::init();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let project = match args.iter().map(|a| a.as_str()).collect::<Vec<_>>()[..] {
+        ["build", project] => return build(project, false),
+        ["build", "--release", project] => return build(project, true),
+        ["list", project] => return list(project),
+        ["batch", ref rest @ ..] => return batch(rest),
+        ["watch", ref rest @ ..] => return watch(rest),
+        ["convert", ref rest @ ..] => return convert(rest),
+        ["-h"] | ["--help"] => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        [project] => Some(project.to_owned()),
+        [] => None,
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
     let options = eframe::NativeOptions {
         decorated: true,
-        initial_window_size: Some(egui::vec2(500.0, 950.0)),
+        initial_window_size: Some(egui::vec2(500.0, 700.0)),
+        min_window_size: Some(egui::vec2(400.0, 300.0)),
         resizable: true,
         ..Default::default()
     };
-    let _ = run_native(
+    let result = run_native(
         "IPK Package Builder",
         options,
-        Box::new(|cc| Box::new(IpkBuilder::new(cc))),
+        Box::new(move |cc| {
+            let mut app = IpkBuilder::new(cc);
+            if let Some(project) = project {
+                app.open_project(Path::new(&project));
+            }
+            Box::new(app)
+        }),
     );
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,359 @@
+use anyhow::{bail, Context, Result};
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    path::{Component, Path, PathBuf},
+};
+use tar::{Builder, EntryType, Header};
+use walkdir::WalkDir;
+
+use crate::progress::Progress;
+
+/// Example shown in the manifest text field.
+pub const EXAMPLE: &str = "# <host path> -> <target path> [mode=0755] [owner=user:group]
+# build/app -> /usr/bin/app mode=0755 owner=root:root
+# build/share/ -> /usr/share/app/
+# build/lib/*.so.* -> /usr/lib/
+";
+
+/// One file or directory to be written into data.tar.gz, from the manifest
+/// or the data root.
+pub struct InstallFile {
+    pub src: PathBuf,
+    /// path inside the package, without a leading `/`
+    pub dest: PathBuf,
+    pub is_dir: bool,
+    pub mode: Option<u32>,
+    pub owner: Option<(String, String)>,
+    /// listed in `conffiles`
+    pub conffile: bool,
+}
+
+impl InstallFile {
+    /// Symlinks are packaged as symlinks, not as what they point to.
+    pub fn is_symlink(&self) -> bool {
+        fs::symlink_metadata(&self.src)
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false)
+    }
+}
+
+struct Entry {
+    src: String,
+    dest: String,
+    mode: Option<u32>,
+    owner: Option<(String, String)>,
+}
+
+fn parse_line(line: &str) -> Result<Entry> {
+    let Some((src, rest)) = line.split_once("->") else {
+        bail!("Expected `<host path> -> <target path>`");
+    };
+    let mut words = rest.split_whitespace();
+    let Some(dest) = words.next() else {
+        bail!("Missing target path");
+    };
+    let mut entry = Entry {
+        src: src.trim().to_owned(),
+        dest: dest.to_owned(),
+        mode: None,
+        owner: None,
+    };
+    for word in words {
+        match word.split_once('=') {
+            Some(("mode", mode)) => {
+                entry.mode = Some(
+                    u32::from_str_radix(mode, 8).context(format!("Invalid mode {}", mode))?,
+                )
+            }
+            Some(("owner", owner)) => {
+                let (user, group) = owner.split_once(':').unwrap_or((owner, owner));
+                entry.owner = Some((user.to_owned(), group.to_owned()));
+            }
+            _ => bail!("Unknown option {}", word),
+        }
+    }
+    Ok(entry)
+}
+
+/// Target path relative to the package root. Rejects paths that would
+/// leave it.
+fn target_path(dest: &str) -> Result<PathBuf> {
+    let path = Path::new(dest.trim_start_matches('/'));
+    if path.components().any(|c| !matches!(c, Component::Normal(_))) {
+        bail!("Target path {} must be absolute without `..`", dest);
+    }
+    // collecting the components drops a trailing slash
+    Ok(path.components().collect())
+}
+
+fn file_name(path: &Path) -> Result<&std::ffi::OsStr> {
+    path.file_name()
+        .context(format!("{} has no file name", path.display()))
+}
+
+/// Parse a manifest and resolve it to the files it installs. Host paths are
+/// relative to `base`.
+pub fn resolve(text: &str, base: &Path) -> Result<Vec<InstallFile>> {
+    let mut files = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let entry = parse_line(line).context(format!("Manifest line {}: {}", i + 1, line))?;
+        resolve_entry(&entry, base, &mut files)
+            .context(format!("Manifest line {}: {}", i + 1, line))?;
+    }
+    Ok(files)
+}
+
+/// Host paths the manifest reads from, without resolving them: for a glob
+/// the folder above its first wildcard. Lines that don't parse are skipped.
+pub fn sources(text: &str, base: &Path) -> Vec<PathBuf> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| parse_line(line).ok())
+        .map(|entry| {
+            let literal: PathBuf = Path::new(&entry.src)
+                .components()
+                .take_while(|c| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+                .collect();
+            base.join(literal)
+        })
+        .collect()
+}
+
+/// Call `push` for the directory `src` installed as `dest` and for
+/// everything below it.
+fn push_tree(src: &Path, dest: &Path, push: &mut impl FnMut(PathBuf, PathBuf, bool)) -> Result<()> {
+    for item in WalkDir::new(src).sort_by_file_name() {
+        let item = item.context(format!("Could not walk {}", src.display()))?;
+        let relative = item.path().strip_prefix(src)?;
+        if relative.as_os_str().is_empty() {
+            push(item.path().to_owned(), dest.to_owned(), true);
+        } else {
+            push(item.path().to_owned(), dest.join(relative), item.file_type().is_dir());
+        }
+    }
+    Ok(())
+}
+
+fn resolve_entry(entry: &Entry, base: &Path, files: &mut Vec<InstallFile>) -> Result<()> {
+    let into_dir = entry.dest.ends_with('/');
+    let dest = target_path(&entry.dest)?;
+    let pattern = base.join(&entry.src);
+    let mut push = |src: PathBuf, dest: PathBuf, is_dir: bool| {
+        files.push(InstallFile {
+            src,
+            dest,
+            is_dir,
+            mode: entry.mode,
+            owner: entry.owner.clone(),
+            conffile: false,
+        })
+    };
+
+    if entry.src.contains(['*', '?', '[']) {
+        let pattern = pattern.to_string_lossy();
+        let mut matched = false;
+        for src in glob::glob(&pattern).context(format!("Invalid pattern {}", pattern))? {
+            let src = src?;
+            let dest = dest.join(file_name(&src)?);
+            if fs::symlink_metadata(&src)?.is_dir() {
+                push_tree(&src, &dest, &mut push)?;
+            } else {
+                push(src, dest, false);
+            }
+            matched = true;
+        }
+        if !matched {
+            bail!("{} matches no files", pattern);
+        }
+        return Ok(());
+    }
+
+    // a path named in the manifest is followed if it is a symlink, the ones
+    // found below it are packaged as symlinks
+    let named = pattern;
+    let pattern = fs::canonicalize(&named).context(format!("Could not read {}", named.display()))?;
+    let meta = fs::metadata(&pattern).context(format!("Could not read {}", pattern.display()))?;
+    if meta.is_dir() {
+        push_tree(&pattern, &dest, &mut push)?;
+    } else if into_dir {
+        let dest = dest.join(file_name(&named)?);
+        push(pattern, dest, false);
+    } else {
+        push(pattern, dest, false);
+    }
+    Ok(())
+}
+
+/// Numeric ids are taken as they are. For names the id is left at 0 and
+/// opkg resolves the user and group by name when installing.
+fn owner_id(name: &str) -> u64 {
+    name.parse().unwrap_or(0)
+}
+
+fn header_for(file: &InstallFile) -> Result<Header> {
+    let meta = fs::symlink_metadata(&file.src).context(format!("Could not read {}", file.src.display()))?;
+    let mut header = Header::new_gnu();
+    header.set_metadata(&meta);
+    if meta.file_type().is_symlink() {
+        header.set_entry_type(EntryType::Symlink);
+        header.set_size(0);
+    } else if file.is_dir {
+        header.set_entry_type(EntryType::Directory);
+        header.set_size(0);
+    }
+    header.set_uid(0);
+    header.set_gid(0);
+    if let Some(mode) = file.mode {
+        header.set_mode(mode);
+    }
+    if let Some((user, group)) = &file.owner {
+        header.set_uid(owner_id(user));
+        header.set_gid(owner_id(group));
+        header.set_username(user)?;
+        header.set_groupname(group)?;
+    }
+    header.set_cksum();
+    Ok(header)
+}
+
+fn dir_header() -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Directory);
+    header.set_size(0);
+    header.set_mode(0o755);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_cksum();
+    header
+}
+
+/// Append `files` to `arch`, creating missing parent directories. `existing`
+/// holds directories already in the archive and is updated.
+pub fn append<W: std::io::Write>(
+    arch: &mut Builder<W>,
+    files: &[InstallFile],
+    existing: &mut BTreeSet<PathBuf>,
+    progress: &Progress,
+) -> Result<()> {
+    for file in files {
+        progress.check()?;
+        for dir in file.dest.ancestors().skip(1).collect::<Vec<_>>().into_iter().rev() {
+            if dir.as_os_str().is_empty() || existing.contains(dir) {
+                continue;
+            }
+            arch.append_data(&mut dir_header(), dir, std::io::empty())?;
+            existing.insert(dir.to_owned());
+        }
+        let mut header = header_for(file)?;
+        if header.entry_type() == EntryType::Symlink {
+            let target = fs::read_link(&file.src).context(format!("Could not read {}", file.src.display()))?;
+            arch.append_link(&mut header, &file.dest, target)
+                .context(format!("Could not append {}", file.src.display()))?;
+        } else if file.is_dir {
+            if existing.insert(file.dest.clone()) {
+                arch.append_data(&mut header, &file.dest, std::io::empty())?;
+            }
+        } else {
+            let content = File::open(&file.src).context(format!("Could not open {}", file.src.display()))?;
+            arch.append_data(&mut header, &file.dest, progress.reader(content))
+                .context(format!("Could not append {}", file.src.display()))?;
+        }
+        progress.file_done();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch;
+
+    fn build_dir(name: &str) -> PathBuf {
+        let base = scratch(&format!("manifest-{}", name));
+        fs::create_dir_all(base.join("build/share/icons")).unwrap();
+        fs::create_dir_all(base.join("build/lib")).unwrap();
+        for file in ["build/app", "build/share/app.desktop", "build/share/icons/app.png", "build/lib/libapp.so.1"] {
+            fs::write(base.join(file), file).unwrap();
+        }
+        base
+    }
+
+    fn installed(files: &[InstallFile]) -> Vec<String> {
+        files
+            .iter()
+            .map(|f| format!("{}{}", f.dest.display(), if f.is_dir { "/" } else { "" }))
+            .collect()
+    }
+
+    #[test]
+    fn parses_options() {
+        let entry = parse_line("build/app -> /usr/bin/app mode=0755 owner=root:wheel").unwrap();
+        assert_eq!(entry.src, "build/app");
+        assert_eq!(entry.dest, "/usr/bin/app");
+        assert_eq!(entry.mode, Some(0o755));
+        assert_eq!(entry.owner, Some(("root".to_owned(), "wheel".to_owned())));
+        let entry = parse_line("a -> /b owner=app").unwrap();
+        assert_eq!(entry.owner, Some(("app".to_owned(), "app".to_owned())));
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        assert!(parse_line("build/app /usr/bin/app").is_err());
+        assert!(parse_line("build/app ->").is_err());
+        assert!(parse_line("a -> /b mode=999").is_err());
+        assert!(parse_line("a -> /b color=red").is_err());
+        assert!(target_path("/usr/../etc/passwd").is_err());
+    }
+
+    #[test]
+    fn resolves_files_and_folders() {
+        let base = build_dir("plain");
+        let files = resolve("# comment\nbuild/app -> /usr/bin/\nbuild/share/ -> /usr/share/app/\n", &base).unwrap();
+        assert_eq!(
+            installed(&files),
+            [
+                "usr/bin/app",
+                "usr/share/app/",
+                "usr/share/app/app.desktop",
+                "usr/share/app/icons/",
+                "usr/share/app/icons/app.png",
+            ]
+        );
+        let Err(error) = resolve("\nbuild/missing -> /usr/bin/missing\n", &base) else {
+            panic!("missing source resolved");
+        };
+        assert!(format!("{:#}", error).starts_with("Manifest line 2"), "{:#}", error);
+    }
+
+    #[test]
+    fn sources_stop_at_the_first_wildcard() {
+        let base = Path::new("/p");
+        assert_eq!(
+            sources("# comment\nbuild/app -> /usr/bin/\nbuild/lib/*.so.* -> /usr/lib/\nnot a line\n", base),
+            [PathBuf::from("/p/build/app"), PathBuf::from("/p/build/lib")]
+        );
+    }
+
+    #[test]
+    fn globs_expand_folders() {
+        let base = build_dir("glob");
+        let files = resolve("build/s* -> /usr/\nbuild/lib/*.so.* -> /usr/lib/\n", &base).unwrap();
+        assert_eq!(
+            installed(&files),
+            [
+                "usr/share/",
+                "usr/share/app.desktop",
+                "usr/share/icons/",
+                "usr/share/icons/app.png",
+                "usr/lib/libapp.so.1",
+            ]
+        );
+        assert!(resolve("build/*.txt -> /usr/", &base).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::{bail, Result};
+use std::{
+    fmt,
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+#[derive(Default)]
+struct Shared {
+    cancelled: AtomicBool,
+    files_done: AtomicUsize,
+    files_total: AtomicUsize,
+    bytes: AtomicU64,
+    stage: Mutex<String>,
+}
+
+/// Progress of a build, shared between the thread running it and the one
+/// showing it. Cloning gives another handle to the same build.
+#[derive(Clone, Default)]
+pub struct Progress {
+    shared: Arc<Shared>,
+}
+
+/// What [`Progress::report`] returns.
+pub struct Report {
+    pub stage: String,
+    pub files_done: usize,
+    pub files_total: usize,
+    pub bytes: u64,
+}
+
+impl Report {
+    /// Share of the files of the current stage done, 0 before it knows any.
+    pub fn fraction(&self) -> f32 {
+        if self.files_total == 0 {
+            0.
+        } else {
+            self.files_done as f32 / self.files_total as f32
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}/{} files, {} KiB",
+            self.stage,
+            self.files_done,
+            self.files_total,
+            self.bytes / 1024
+        )
+    }
+}
+
+impl Progress {
+    /// Ask the build to stop. It does so at the next file or read.
+    pub fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Error out if the build was cancelled.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            bail!("Build cancelled");
+        }
+        Ok(())
+    }
+
+    /// Start a new stage, e.g. one package of a split build, with `files` to go.
+    pub fn start(&self, stage: impl Into<String>, files: usize) {
+        *self.shared.stage.lock().unwrap_or_else(|e| e.into_inner()) = stage.into();
+        self.shared.files_done.store(0, Ordering::Relaxed);
+        self.shared.files_total.store(files, Ordering::Relaxed);
+    }
+
+    pub fn file_done(&self) {
+        self.shared.files_done.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> Report {
+        Report {
+            stage: self.shared.stage.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+            files_done: self.shared.files_done.load(Ordering::Relaxed),
+            files_total: self.shared.files_total.load(Ordering::Relaxed),
+            bytes: self.shared.bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Wrap `inner` so reading from it counts bytes and stops once the build
+    /// is cancelled.
+    pub fn reader<R: Read>(&self, inner: R) -> ProgressReader<R> {
+        ProgressReader {
+            inner,
+            progress: self.clone(),
+        }
+    }
+}
+
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Progress,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.progress.is_cancelled() {
+            return Err(io::Error::other("Build cancelled"));
+        }
+        let n = self.inner.read(buf)?;
+        self.progress.shared.bytes.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_files_and_bytes() {
+        let progress = Progress::default();
+        assert_eq!(progress.report().fraction(), 0.);
+        progress.start("app", 4);
+        progress.file_done();
+        let mut content = Vec::new();
+        progress.reader(&[0u8; 3000][..]).read_to_end(&mut content).unwrap();
+        let report = progress.report();
+        assert_eq!(report.fraction(), 0.25);
+        assert_eq!(report.to_string(), "app: 1/4 files, 2 KiB");
+        // a new stage starts counting files again
+        progress.start("app-dev", 2);
+        assert_eq!(progress.report().fraction(), 0.);
+    }
+
+    #[test]
+    fn cancel_stops_reads_and_checks() {
+        let progress = Progress::default();
+        let mut reader = progress.clone().reader(&b"content"[..]);
+        assert!(progress.check().is_ok());
+        progress.cancel();
+        assert!(progress.check().is_err());
+        assert!(reader.read(&mut [0; 4]).is_err());
+    }
+}
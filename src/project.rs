@@ -0,0 +1,129 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::ui::IpkBuilder;
+
+/// Bumped whenever the project file layout changes incompatibly.
+pub const PROJECT_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct ProjectFile<'a> {
+    version: u32,
+    #[serde(flatten)]
+    builder: &'a IpkBuilder,
+}
+
+#[derive(Deserialize)]
+struct ProjectHeader {
+    version: u32,
+}
+
+/// Apply `f` to every path stored in the builder state.
+fn map_paths(builder: &mut IpkBuilder, f: impl Fn(&Path) -> PathBuf) {
+    for file in [
+        &mut builder.control_file,
+        &mut builder.debian_binary,
+        &mut builder.postinst,
+        &mut builder.preinst,
+        &mut builder.prerm,
+        &mut builder.manifest,
+    ] {
+        if let Some(path) = &mut file.picked_path {
+            *path = f(path);
+        }
+    }
+    for path in [&mut builder.data_path, &mut builder.output_path]
+        .into_iter()
+        .flatten()
+    {
+        *path = f(Path::new(path)).display().to_string();
+    }
+    for path in &mut builder.snippets.dirs {
+        *path = f(path);
+    }
+    for path in [
+        &mut builder.service.file,
+        &mut builder.shlibdeps.feed_index,
+        &mut builder.shlibdeps.contents_index,
+        &mut builder.shlibdeps.soname_map,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        *path = f(path);
+    }
+}
+
+fn project_dir(project: &Path) -> PathBuf {
+    match project.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// True if `path` is a project file, as opposed to other TOML files like a
+/// `Cargo.toml` found next to it.
+pub fn is_project(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|text| toml::from_str::<ProjectHeader>(&text).ok())
+        .is_some()
+}
+
+/// Read a project file. Relative paths in it are resolved against the
+/// directory the project file lives in.
+pub fn load<P: AsRef<Path>>(project: P) -> Result<IpkBuilder> {
+    let project = project.as_ref();
+    let text = fs::read_to_string(project)
+        .context(format!("Could not read project file {}", project.display()))?;
+    let header: ProjectHeader = toml::from_str(&text)
+        .context(format!("{} is not a project file", project.display()))?;
+    if header.version > PROJECT_VERSION {
+        bail!(
+            "{} was written by a newer version (project version {}, supported up to {})",
+            project.display(),
+            header.version,
+            PROJECT_VERSION
+        );
+    }
+    let mut builder: IpkBuilder = toml::from_str(&text)
+        .context(format!("Could not parse project file {}", project.display()))?;
+    let dir = project_dir(project);
+    map_paths(&mut builder, |p| dir.join(p));
+    builder.project_path = Some(project.to_owned());
+    Ok(builder)
+}
+
+/// Owned copy of the settings in `builder`, e.g. to build on another thread.
+/// Runtime state such as the watcher is not copied.
+pub fn snapshot(builder: &IpkBuilder) -> Result<IpkBuilder> {
+    // round trip through toml, every setting is serialized anyway
+    let mut copy: IpkBuilder = toml::Value::try_from(builder)?.try_into()?;
+    copy.project_path = builder.project_path.clone();
+    Ok(copy)
+}
+
+/// Write the builder state to a project file, with every path stored
+/// relative to the directory of the project file.
+pub fn save<P: AsRef<Path>>(builder: &IpkBuilder, project: P) -> Result<()> {
+    let project = project.as_ref();
+    let dir = std::path::absolute(project_dir(project))?;
+    let mut relative = snapshot(builder)?;
+    map_paths(&mut relative, |p| {
+        std::path::absolute(p)
+            .ok()
+            .and_then(|p| pathdiff::diff_paths(p, &dir))
+            .unwrap_or_else(|| p.to_owned())
+    });
+    let text = toml::to_string_pretty(&ProjectFile {
+        version: PROJECT_VERSION,
+        builder: &relative,
+    })?;
+    fs::write(project, text)
+        .context(format!("Could not write project file {}", project.display()))?;
+    Ok(())
+}
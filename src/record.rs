@@ -0,0 +1,126 @@
+use anyhow::{Context, Result};
+use flate2::Compression;
+use log::info;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    control::ControlFile,
+    ui::{FileOrPath, IpkBuilder, ScriptSource},
+    verify::PackagedFile,
+    BuiltPackage,
+};
+
+/// How the archives in the package are compressed.
+#[derive(Serialize)]
+pub struct CompressionSettings {
+    pub format: &'static str,
+    pub level: u32,
+}
+
+/// Host paths the package was built from. `None` for inputs typed into the
+/// GUI or not used.
+#[derive(Serialize)]
+pub struct Inputs {
+    pub project: Option<PathBuf>,
+    pub control: Option<PathBuf>,
+    pub debian_binary: Option<PathBuf>,
+    pub scripts: BTreeMap<String, PathBuf>,
+    pub data_root: Option<PathBuf>,
+    pub manifest: Option<PathBuf>,
+    pub service: Option<PathBuf>,
+}
+
+/// Machine-readable account of one build, written next to the package.
+#[derive(Serialize)]
+pub struct BuildRecord {
+    pub package: String,
+    pub sha256: String,
+    pub size: u64,
+    /// name and version of the program that built the package
+    pub tool: String,
+    pub started: String,
+    pub finished: String,
+    pub control: BTreeMap<String, String>,
+    pub compression: CompressionSettings,
+    pub inputs: Inputs,
+    pub files: Vec<PackagedFile>,
+}
+
+fn picked(file: &FileOrPath) -> Option<PathBuf> {
+    match file.file_or_text {
+        ScriptSource::FromPath => file.picked_path.clone(),
+        ScriptSource::FromTextfield => None,
+    }
+}
+
+fn inputs(data: &IpkBuilder) -> Inputs {
+    let scripts = [
+        ("postinst", &data.postinst),
+        ("preinst", &data.preinst),
+        ("prerm", &data.prerm),
+    ]
+    .into_iter()
+    .filter(|(_, script)| script.enabled)
+    .filter_map(|(name, script)| Some((name.to_owned(), picked(script)?)))
+    .collect();
+    Inputs {
+        project: data.project_path.clone(),
+        control: picked(&data.control_file),
+        debian_binary: if data.debian_binary.enabled { None } else { picked(&data.debian_binary) },
+        scripts,
+        data_root: data.data_path.as_ref().map(PathBuf::from),
+        manifest: if data.manifest.enabled { picked(&data.manifest) } else { None },
+        service: if data.service.enabled { data.service.file.clone() } else { None },
+    }
+}
+
+/// The `<package>.sha256` and `<package>.json` files next to `package`.
+pub fn paths(package: &Path) -> [PathBuf; 2] {
+    let name = package.file_name().unwrap_or_default().to_string_lossy();
+    [
+        package.with_file_name(format!("{}.sha256", name)),
+        package.with_file_name(format!("{}.json", name)),
+    ]
+}
+
+/// Write the checksum file, in the format `sha256sum -c` reads, and the
+/// build record of `built` to `checksum_path` and `record_path`, usually
+/// the [`paths`] of the package.
+pub fn write(
+    data: &IpkBuilder,
+    built: &BuiltPackage,
+    started: SystemTime,
+    [checksum_path, record_path]: [&Path; 2],
+) -> Result<()> {
+    let package = &built.path;
+    let name = package.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+    fs::write(checksum_path, format!("{}  {}\n", built.sha256, name))
+        .context(format!("Could not write {}", checksum_path.display()))?;
+
+    let record = BuildRecord {
+        package: name,
+        sha256: built.sha256.clone(),
+        size: built.size,
+        tool: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+        started: humantime::format_rfc3339_seconds(started).to_string(),
+        finished: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        control: ControlFile::parse(&built.control).fields.into_iter().collect(),
+        compression: CompressionSettings {
+            format: "gzip",
+            level: Compression::default().level(),
+        },
+        inputs: inputs(data),
+        files: built.verification.files.clone(),
+    };
+    fs::write(record_path, serde_json::to_string_pretty(&record)?)
+        .context(format!("Could not write {}", record_path.display()))?;
+    info!("Wrote {} and {}", checksum_path.display(), record_path.display());
+    Ok(())
+}
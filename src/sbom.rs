@@ -0,0 +1,350 @@
+use anyhow::{Context, Result};
+use log::info;
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{control::ControlFile, BuiltPackage};
+
+/// Fields whose packages must be installed for this one to work.
+const DEPENDENCY_FIELDS: &[&str] = &["Pre-Depends", "Depends"];
+
+/// Facts about the package both formats need.
+struct Subject<'a> {
+    file_name: String,
+    sha256: String,
+    name: String,
+    version: String,
+    license: Option<String>,
+    /// maintainer name and email
+    supplier: Option<(String, Option<String>)>,
+    dependencies: Vec<String>,
+    built: &'a BuiltPackage,
+    created: String,
+}
+
+/// Names of the packages declared in the dependency fields. Every
+/// alternative of `a | b` counts, versions and architecture qualifiers are
+/// dropped.
+fn dependencies(control: &ControlFile) -> Vec<String> {
+    let mut names = Vec::new();
+    for field in DEPENDENCY_FIELDS {
+        for relation in control.get(field).unwrap_or_default().split([',', '|']) {
+            let name = relation.split(['(', ':']).next().unwrap_or_default().trim();
+            if !name.is_empty() && !names.iter().any(|n| n == name) {
+                names.push(name.to_owned());
+            }
+        }
+    }
+    names
+}
+
+/// `Name <email>` split into its parts.
+fn maintainer(value: &str) -> (String, Option<String>) {
+    match value.split_once('<') {
+        Some((name, email)) => (
+            name.trim().to_owned(),
+            Some(email.trim_end_matches('>').trim().to_owned()),
+        ),
+        None if value.contains('@') => (value.trim().to_owned(), Some(value.trim().to_owned())),
+        None => (value.trim().to_owned(), None),
+    }
+}
+
+/// SPDX license list identifiers `License` fields commonly use. Anything
+/// else in a license expression is taken as a custom license.
+const SPDX_LICENSES: &[&str] = &[
+    "0BSD", "AGPL-3.0-only", "AGPL-3.0-or-later", "Apache-2.0", "Artistic-2.0", "BSD-2-Clause",
+    "BSD-3-Clause", "BSL-1.0", "CC0-1.0", "CC-BY-4.0", "CC-BY-SA-4.0", "EPL-2.0", "GPL-1.0-only",
+    "GPL-1.0-or-later", "GPL-2.0", "GPL-2.0+", "GPL-2.0-only", "GPL-2.0-or-later", "GPL-3.0",
+    "GPL-3.0+", "GPL-3.0-only", "GPL-3.0-or-later", "ISC", "LGPL-2.0-only", "LGPL-2.0-or-later",
+    "LGPL-2.1", "LGPL-2.1+", "LGPL-2.1-only", "LGPL-2.1-or-later", "LGPL-3.0", "LGPL-3.0+",
+    "LGPL-3.0-only", "LGPL-3.0-or-later", "MIT", "MIT-0", "MPL-2.0", "OpenSSL", "PSF-2.0",
+    "Python-2.0", "Unlicense", "Zlib",
+];
+
+/// License exceptions allowed after `WITH`.
+const SPDX_EXCEPTIONS: &[&str] = &[
+    "Autoconf-exception-3.0", "Bison-exception-2.2", "Classpath-exception-2.0",
+    "GCC-exception-3.1", "LLVM-exception", "Linux-syscall-note", "OpenSSL-exception",
+];
+
+/// True if `value` is an SPDX license expression over known identifiers,
+/// e.g. `MIT OR Apache-2.0` or `GPL-2.0-only WITH Linux-syscall-note`.
+fn is_spdx_expression(value: &str) -> bool {
+    let spaced = value.replace('(', " ( ").replace(')', " ) ");
+    let mut depth = 0;
+    // true after a license or a closing parenthesis
+    let mut operand = false;
+    let mut after_with = false;
+    for token in spaced.split_whitespace() {
+        let valid = match token {
+            "(" => !operand && !after_with,
+            ")" => operand && depth > 0,
+            "AND" | "OR" | "WITH" => operand,
+            id if after_with => SPDX_EXCEPTIONS.contains(&id),
+            id => !operand && (SPDX_LICENSES.contains(&id) || id.starts_with("LicenseRef-")),
+        };
+        if !valid {
+            return false;
+        }
+        match token {
+            "(" => depth += 1,
+            ")" => depth -= 1,
+            _ => {}
+        }
+        operand = !matches!(token, "(" | "AND" | "OR" | "WITH");
+        after_with = token == "WITH";
+    }
+    operand && depth == 0
+}
+
+/// `licenseDeclared` for `license`, and the extracted licensing info a
+/// custom license needs.
+fn declared_license(license: Option<&str>) -> (String, Option<Value>) {
+    match license {
+        None => ("NOASSERTION".to_owned(), None),
+        Some(license) if is_spdx_expression(license) => (license.to_owned(), None),
+        Some(license) => {
+            let id = spdx_id("LicenseRef", license).replacen("SPDXRef-", "", 1);
+            let info = json!({ "licenseId": id, "name": license, "extractedText": license });
+            (id, Some(info))
+        }
+    }
+}
+
+/// SPDX identifiers may only hold letters, digits, `.` and `-`.
+fn spdx_id(kind: &str, name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' { c } else { '-' })
+        .collect();
+    format!("SPDXRef-{}-{}", kind, name)
+}
+
+fn spdx(subject: &Subject) -> Value {
+    let package_id = spdx_id("Package", &subject.name);
+    let files = &subject.built.verification.files;
+    // SPDX 2.3 section 7.9: SHA-1 over the sorted SHA-1s of all files
+    let mut sha1s: Vec<&str> = files.iter().map(|f| f.sha1.as_str()).collect();
+    sha1s.sort();
+    let verification_code = format!("{:x}", Sha1::digest(sha1s.concat()));
+    let (license, extracted) = declared_license(subject.license.as_deref());
+
+    let mut packages = vec![json!({
+        "SPDXID": package_id,
+        "name": subject.name,
+        "versionInfo": subject.version,
+        "packageFileName": subject.file_name,
+        "supplier": match &subject.supplier {
+            Some((name, Some(email))) => format!("Person: {} ({})", name, email),
+            Some((name, None)) => format!("Person: {}", name),
+            None => "NOASSERTION".to_owned(),
+        },
+        "downloadLocation": "NOASSERTION",
+        "filesAnalyzed": true,
+        "packageVerificationCode": { "packageVerificationCodeValue": verification_code },
+        "checksums": [{ "algorithm": "SHA256", "checksumValue": subject.sha256 }],
+        "licenseConcluded": "NOASSERTION",
+        "licenseDeclared": license,
+        "copyrightText": "NOASSERTION",
+    })];
+    let mut relationships = vec![json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": package_id,
+    })];
+    let files: Vec<Value> = files
+        .iter()
+        .enumerate()
+        .map(|(i, file)| {
+            let file_id = format!("SPDXRef-File-{}", i + 1);
+            relationships.push(json!({
+                "spdxElementId": package_id,
+                "relationshipType": "CONTAINS",
+                "relatedSpdxElement": file_id,
+            }));
+            json!({
+                "SPDXID": file_id,
+                "fileName": format!("./{}", file.path.display()),
+                "checksums": [
+                    { "algorithm": "SHA1", "checksumValue": file.sha1 },
+                    { "algorithm": "SHA256", "checksumValue": file.sha256 },
+                ],
+                "licenseConcluded": "NOASSERTION",
+                "copyrightText": "NOASSERTION",
+            })
+        })
+        .collect();
+    for dependency in &subject.dependencies {
+        let dependency_id = spdx_id("Dependency", dependency);
+        packages.push(json!({
+            "SPDXID": dependency_id,
+            "name": dependency,
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+        }));
+        relationships.push(json!({
+            "spdxElementId": package_id,
+            "relationshipType": "DEPENDS_ON",
+            "relatedSpdxElement": dependency_id,
+        }));
+    }
+
+    let mut document = json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": format!("{}-{}", subject.name, subject.version),
+        "documentNamespace": format!(
+            "https://spdx.org/spdxdocs/{}-{}-{}",
+            subject.name, subject.version, subject.sha256
+        ),
+        "creationInfo": {
+            "created": subject.created,
+            "creators": [format!("Tool: {}-{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "files": files,
+        "relationships": relationships,
+    });
+    if let Some(extracted) = extracted {
+        document["hasExtractedLicensingInfos"] = json!([extracted]);
+    }
+    document
+}
+
+fn cyclonedx(subject: &Subject) -> Value {
+    let package_ref = format!("{}@{}", subject.name, subject.version);
+    let mut component = json!({
+        "type": "application",
+        "bom-ref": package_ref,
+        "name": subject.name,
+        "version": subject.version,
+        "hashes": [{ "alg": "SHA-256", "content": subject.sha256 }],
+    });
+    if let Some((name, email)) = &subject.supplier {
+        component["supplier"] = match email {
+            Some(email) => json!({ "name": name, "contact": [{ "name": name, "email": email }] }),
+            None => json!({ "name": name }),
+        };
+    }
+    if let Some(license) = &subject.license {
+        component["licenses"] = if is_spdx_expression(license) {
+            json!([{ "expression": license }])
+        } else {
+            json!([{ "license": { "name": license } }])
+        };
+    }
+    let mut components: Vec<Value> = subject
+        .built
+        .verification
+        .files
+        .iter()
+        .map(|file| {
+            json!({
+                "type": "file",
+                "bom-ref": format!("file:{}", file.path.display()),
+                "name": format!("/{}", file.path.display()),
+                "hashes": [
+                    { "alg": "SHA-1", "content": file.sha1 },
+                    { "alg": "SHA-256", "content": file.sha256 },
+                ],
+            })
+        })
+        .collect();
+    let mut depends_on = Vec::new();
+    for dependency in &subject.dependencies {
+        let dependency_ref = format!("package:{}", dependency);
+        components.push(json!({
+            "type": "library",
+            "bom-ref": dependency_ref,
+            "name": dependency,
+        }));
+        depends_on.push(dependency_ref);
+    }
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": subject.created,
+            "tools": [{ "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") }],
+            "component": component,
+        },
+        "components": components,
+        "dependencies": [{ "ref": package_ref, "dependsOn": depends_on }],
+    })
+}
+
+/// The `<package>.spdx.json` and `<package>.cdx.json` files next to `package`.
+pub fn paths(package: &Path) -> [PathBuf; 2] {
+    let name = package.file_name().unwrap_or_default().to_string_lossy();
+    [
+        package.with_file_name(format!("{}.spdx.json", name)),
+        package.with_file_name(format!("{}.cdx.json", name)),
+    ]
+}
+
+/// Write the SPDX 2.3 and CycloneDX 1.5 documents describing `built` to
+/// `spdx_path` and `cyclonedx_path`, usually the [`paths`] of the package.
+pub fn write(built: &BuiltPackage, [spdx_path, cyclonedx_path]: [&Path; 2]) -> Result<()> {
+    let package = &built.path;
+    let control = ControlFile::parse(&built.control);
+    let field = |key| control.get(key).filter(|v| !v.is_empty()).map(|v| v.to_owned());
+    let subject = Subject {
+        file_name: package.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+        sha256: built.sha256.clone(),
+        name: field("Package").unwrap_or_default(),
+        version: field("Version").unwrap_or_default(),
+        license: field("License"),
+        supplier: field("Maintainer").map(|m| maintainer(&m)),
+        dependencies: dependencies(&control),
+        built,
+        created: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+    };
+    for (path, document) in [(spdx_path, spdx(&subject)), (cyclonedx_path, cyclonedx(&subject))] {
+        fs::write(path, serde_json::to_string_pretty(&document)?)
+            .context(format!("Could not write {}", path.display()))?;
+    }
+    info!("Wrote SBOMs {} and {}", spdx_path.display(), cyclonedx_path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spdx_expressions() {
+        for valid in ["MIT", "MIT OR Apache-2.0", "(MIT OR Apache-2.0) AND Zlib", "GPL-2.0-only WITH Linux-syscall-note", "LicenseRef-Vendor"] {
+            assert!(is_spdx_expression(valid), "{}", valid);
+        }
+        for invalid in ["", "GPLv2", "MIT or Apache-2.0", "MIT OR", "(MIT", "MIT)", "MIT Apache-2.0", "MIT WITH Zlib", "Proprietary"] {
+            assert!(!is_spdx_expression(invalid), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn custom_licenses_get_a_license_ref() {
+        assert_eq!(declared_license(None), ("NOASSERTION".to_owned(), None));
+        assert_eq!(declared_license(Some("MIT")), ("MIT".to_owned(), None));
+        let (id, info) = declared_license(Some("GPLv2 or later"));
+        assert_eq!(id, "LicenseRef-GPLv2-or-later");
+        assert_eq!(info.unwrap()["extractedText"], "GPLv2 or later");
+    }
+
+    #[test]
+    fn maintainers_and_dependencies() {
+        assert_eq!(maintainer("Jo Doe <jo@example.com>"), ("Jo Doe".to_owned(), Some("jo@example.com".to_owned())));
+        assert_eq!(maintainer("jo@example.com").1.as_deref(), Some("jo@example.com"));
+        assert_eq!(maintainer("Jo").1, None);
+        let control = ControlFile::parse("Pre-Depends: busybox\nDepends: libc (>= 1.2), libssl3 | libssl1.1, python3:any, busybox\n");
+        assert_eq!(dependencies(&control), ["busybox", "libc", "libssl3", "libssl1.1", "python3"]);
+    }
+}
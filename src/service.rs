@@ -0,0 +1,285 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::manifest::InstallFile;
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum InitSystem {
+    #[default]
+    Systemd,
+    /// init script enabled through `update-rc.d`
+    SysV,
+    /// OpenWrt init script using `/etc/rc.common`
+    Procd,
+}
+
+impl InitSystem {
+    pub const ALL: [InitSystem; 3] = [InitSystem::Systemd, InitSystem::SysV, InitSystem::Procd];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            InitSystem::Systemd => "systemd unit",
+            InitSystem::SysV => "SysV init script",
+            InitSystem::Procd => "procd init script",
+        }
+    }
+}
+
+/// A daemon shipped in the package: its unit or init script, and the
+/// maintainer script logic enabling, starting and stopping it.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Service {
+    pub enabled: bool,
+    pub init: InitSystem,
+    /// unit file or init script on the host
+    pub file: Option<PathBuf>,
+    pub name: String,
+    /// enable and start the service when the package is installed
+    pub enable_on_install: bool,
+    /// restart a running service when the package is upgraded
+    pub restart_on_upgrade: bool,
+}
+
+impl Default for Service {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            init: InitSystem::Systemd,
+            file: None,
+            name: String::new(),
+            enable_on_install: true,
+            restart_on_upgrade: true,
+        }
+    }
+}
+
+impl Service {
+    fn check(&self) -> Result<()> {
+        if self.name.is_empty() || self.name.contains(['/', ' ']) {
+            bail!("Service name {:?} must be a single word", self.name);
+        }
+        Ok(())
+    }
+
+    /// The unit file or init script at its path in the package.
+    pub fn install_file(&self) -> Result<Option<InstallFile>> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        self.check()?;
+        let Some(src) = self.file.clone() else {
+            bail!("No file picked for service {}", self.name);
+        };
+        let (dest, mode) = match self.init {
+            InitSystem::Systemd => (format!("lib/systemd/system/{}.service", self.name), 0o644),
+            InitSystem::SysV | InitSystem::Procd => (format!("etc/init.d/{}", self.name), 0o755),
+        };
+        Ok(Some(InstallFile {
+            src,
+            dest: dest.into(),
+            is_dir: false,
+            mode: Some(mode),
+            owner: None,
+            conffile: false,
+        }))
+    }
+
+    /// Commands for each action of `script`, e.g. `configure` of postinst.
+    /// Commands marked `true` only run on the live system; the others,
+    /// enabling and disabling, work on the root file system of the package,
+    /// `$IPKG_INSTROOT` while an image is built.
+    fn commands(&self, script: &str) -> Vec<(&'static str, Vec<(bool, String)>)> {
+        let name = &self.name;
+        let init = format!("/etc/init.d/{}", name);
+        let systemctl_root = "systemctl ${IPKG_INSTROOT:+--root=\"$IPKG_INSTROOT\"}";
+        let (enable, start, restart, try_restart, stop, disable, cleanup) = match self.init {
+            InitSystem::Systemd => (
+                format!("{} enable {}.service", systemctl_root, name),
+                format!("systemctl start {}.service", name),
+                format!("systemctl restart {}.service", name),
+                format!("systemctl try-restart {}.service", name),
+                format!("systemctl stop {}.service || true", name),
+                format!("{} disable {}.service || true", systemctl_root, name),
+                Some("systemctl daemon-reload".to_owned()),
+            ),
+            // update-rc.d knows no root, it only runs on the live system
+            InitSystem::SysV => (
+                format!("update-rc.d {} defaults", name),
+                format!("{} start", init),
+                format!("{} restart", init),
+                format!("if {} status >/dev/null 2>&1; then {} restart; fi", init, init),
+                format!("{} stop || true", init),
+                String::new(),
+                Some(format!("update-rc.d {} remove", name)),
+            ),
+            InitSystem::Procd => (
+                format!("\"$IPKG_INSTROOT\"{} enable", init),
+                format!("{} start", init),
+                format!("{} restart", init),
+                format!("if {} running; then {} restart; fi", init, init),
+                format!("{} stop || true", init),
+                format!("\"$IPKG_INSTROOT\"{} disable || true", init),
+                None,
+            ),
+        };
+        let mut commands = Vec::new();
+        match script {
+            "postinst" => {
+                let mut configure = Vec::new();
+                if self.enable_on_install {
+                    configure.push((self.init == InitSystem::SysV, enable));
+                }
+                if self.init == InitSystem::Systemd {
+                    configure.push((true, "systemctl daemon-reload".to_owned()));
+                }
+                match (self.enable_on_install, self.restart_on_upgrade) {
+                    (true, true) => configure.push((true, restart)),
+                    (true, false) => configure.push((true, start)),
+                    (false, true) => configure.push((true, try_restart)),
+                    (false, false) => {}
+                }
+                commands.push(("configure", configure));
+            }
+            "prerm" => {
+                let mut remove = vec![(true, stop)];
+                if !disable.is_empty() {
+                    remove.push((false, disable));
+                }
+                commands.push(("remove", remove));
+            }
+            "postrm" => {
+                if let Some(cleanup) = cleanup {
+                    commands.push(("remove", vec![(true, cleanup)]));
+                }
+            }
+            _ => {}
+        }
+        commands.retain(|(_, c)| !c.is_empty());
+        commands
+    }
+
+    /// `case "$1"` block for `script`, or `None` if the service needs nothing
+    /// there.
+    pub fn block(&self, script: &str) -> Result<Option<String>> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        self.check()?;
+        let commands = self.commands(script);
+        if commands.is_empty() {
+            return Ok(None);
+        }
+        // leave the init system of the build host alone while a root file
+        // system image is built
+        let guard = match self.init {
+            InitSystem::Systemd => "[ -z \"$IPKG_INSTROOT\" ] && [ -d /run/systemd/system ]",
+            InitSystem::SysV | InitSystem::Procd => "[ -z \"$IPKG_INSTROOT\" ]",
+        };
+        let mut block = "case \"$1\" in\n".to_owned();
+        for (action, lines) in commands {
+            block.push_str(&format!("    {})\n        # service {}\n", action, self.name));
+            let mut guarded = false;
+            for (live, line) in lines {
+                if live != guarded {
+                    block.push_str(if live { "        if " } else { "        fi\n" });
+                    if live {
+                        block.push_str(&format!("{}; then\n", guard));
+                    }
+                    guarded = live;
+                }
+                let indent = if live { "            " } else { "        " };
+                block.push_str(&format!("{}{}\n", indent, line));
+            }
+            if guarded {
+                block.push_str("        fi\n");
+            }
+            block.push_str("        ;;\n");
+        }
+        block.push_str("esac\n");
+        Ok(Some(block))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(init: InitSystem) -> Service {
+        Service {
+            enabled: true,
+            init,
+            name: "app".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn systemd_enables_offline_and_restarts_live() {
+        let block = service(InitSystem::Systemd).block("postinst").unwrap().unwrap();
+        assert_eq!(
+            block,
+            "case \"$1\" in
+    configure)
+        # service app
+        systemctl ${IPKG_INSTROOT:+--root=\"$IPKG_INSTROOT\"} enable app.service
+        if [ -z \"$IPKG_INSTROOT\" ] && [ -d /run/systemd/system ]; then
+            systemctl daemon-reload
+            systemctl restart app.service
+        fi
+        ;;
+esac
+"
+        );
+    }
+
+    #[test]
+    fn prerm_stops_live_and_disables_always() {
+        let block = service(InitSystem::Procd).block("prerm").unwrap().unwrap();
+        assert!(block.contains(
+            "        if [ -z \"$IPKG_INSTROOT\" ]; then
+            /etc/init.d/app stop || true
+        fi
+        \"$IPKG_INSTROOT\"/etc/init.d/app disable || true
+"
+        ), "{}", block);
+    }
+
+    #[test]
+    fn sysv_links_only_on_the_live_system() {
+        let service = service(InitSystem::SysV);
+        let block = service.block("postinst").unwrap().unwrap();
+        assert!(block.contains(
+            "        if [ -z \"$IPKG_INSTROOT\" ]; then
+            update-rc.d app defaults
+            /etc/init.d/app restart
+        fi
+"
+        ), "{}", block);
+        let block = service.block("postrm").unwrap().unwrap();
+        assert!(block.contains("            update-rc.d app remove\n        fi\n"), "{}", block);
+    }
+
+    #[test]
+    fn nothing_to_do() {
+        let mut service = service(InitSystem::Procd);
+        assert_eq!(service.block("postrm").unwrap(), None);
+        assert_eq!(service.block("preinst").unwrap(), None);
+        service.enable_on_install = false;
+        service.restart_on_upgrade = false;
+        assert_eq!(service.block("postinst").unwrap(), None);
+        service.name = "my app".to_owned();
+        assert!(service.block("prerm").is_err());
+    }
+
+    #[test]
+    fn installs_the_unit_file() {
+        let mut service = service(InitSystem::Systemd);
+        assert!(service.install_file().is_err());
+        service.file = Some(PathBuf::from("app.service"));
+        let file = service.install_file().unwrap().unwrap();
+        assert_eq!(file.dest, PathBuf::from("lib/systemd/system/app.service"));
+        assert_eq!(file.mode, Some(0o644));
+    }
+}
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Storage key of the session in the eframe storage. The builder state
+/// itself is stored under `eframe::APP_KEY`.
+pub const SESSION_KEY: &str = "session";
+
+const MAX_RECENT_PROJECTS: usize = 10;
+
+/// GUI state that survives a restart but does not belong in a project file.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// most recently used first
+    pub recent_projects: Vec<PathBuf>,
+    pub project_path: Option<PathBuf>,
+    last_file_dir: Option<PathBuf>,
+    last_folder_dir: Option<PathBuf>,
+    last_project_dir: Option<PathBuf>,
+}
+
+fn dialog(dir: &Option<PathBuf>) -> rfd::FileDialog {
+    match dir {
+        Some(dir) => rfd::FileDialog::new().set_directory(dir),
+        None => rfd::FileDialog::new(),
+    }
+}
+
+fn project_dialog(dir: &Option<PathBuf>) -> rfd::FileDialog {
+    dialog(dir).add_filter("IPK builder project", &["toml"])
+}
+
+fn parent_of(path: &Path) -> Option<PathBuf> {
+    path.parent().map(|p| p.to_owned())
+}
+
+impl Session {
+    pub fn pick_file(&mut self) -> Option<PathBuf> {
+        let path = dialog(&self.last_file_dir).pick_file()?;
+        self.last_file_dir = parent_of(&path);
+        Some(path)
+    }
+
+    pub fn pick_folder(&mut self) -> Option<PathBuf> {
+        let path = dialog(&self.last_folder_dir).pick_folder()?;
+        self.last_folder_dir = parent_of(&path);
+        Some(path)
+    }
+
+    pub fn pick_project(&mut self) -> Option<PathBuf> {
+        let path = project_dialog(&self.last_project_dir).pick_file()?;
+        self.last_project_dir = parent_of(&path);
+        Some(path)
+    }
+
+    pub fn save_project_file(&mut self) -> Option<PathBuf> {
+        let path = project_dialog(&self.last_project_dir)
+            .set_file_name("project.toml")
+            .save_file()?;
+        self.last_project_dir = parent_of(&path);
+        Some(path)
+    }
+
+    /// Move `project` to the top of the recent projects list.
+    pub fn add_recent(&mut self, project: &Path) {
+        let project = std::path::absolute(project).unwrap_or_else(|_| project.to_owned());
+        self.recent_projects.retain(|p| *p != project);
+        self.recent_projects.insert(0, project);
+        self.recent_projects.truncate(MAX_RECENT_PROJECTS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recent_projects_move_to_the_top() {
+        let mut session = Session::default();
+        for i in 0..12 {
+            session.add_recent(Path::new(&format!("/projects/{}.toml", i)));
+        }
+        assert_eq!(session.recent_projects.len(), MAX_RECENT_PROJECTS);
+        assert_eq!(session.recent_projects[0], Path::new("/projects/11.toml"));
+        session.add_recent(Path::new("/projects/5.toml"));
+        assert_eq!(session.recent_projects[0], Path::new("/projects/5.toml"));
+        assert_eq!(session.recent_projects.len(), MAX_RECENT_PROJECTS);
+        assert_eq!(session.recent_projects.iter().filter(|p| p.ends_with("5.toml")).count(), 1);
+    }
+
+    #[test]
+    fn recent_paths_are_absolute() {
+        let mut session = Session::default();
+        session.add_recent(Path::new("app.toml"));
+        assert!(session.recent_projects[0].is_absolute());
+    }
+}
@@ -0,0 +1,367 @@
+use eframe::{
+    egui::{self, text::LayoutJob, TextFormat, TextStyle},
+    epaint::Color32,
+};
+
+const KEYWORDS: &[&str] = &[
+    "if", "then", "elif", "else", "fi", "case", "esac", "in", "for", "while", "until", "do",
+    "done", "function", "return", "exit", "local", "set", "export", "shift",
+];
+
+/// Arguments opkg calls each maintainer script with.
+pub fn actions(script: &str) -> &'static [&'static str] {
+    match script {
+        "postinst" => &["configure"],
+        "preinst" => &["install", "upgrade"],
+        "prerm" => &["remove", "upgrade"],
+        "postrm" => &["remove", "upgrade", "purge"],
+        _ => &[],
+    }
+}
+
+/// Something that is likely wrong in a script, with its 1-based line.
+pub struct Problem {
+    pub line: usize,
+    pub message: String,
+}
+
+/// A line with comments removed and the content of single-quoted strings
+/// blanked out. With `blank_double` the content of double-quoted strings
+/// is blanked too.
+fn code_part(line: &str, blank_double: bool) -> String {
+    let mut code = String::with_capacity(line.len());
+    let mut quote = None;
+    let mut prev = ' ';
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '#') if prev.is_whitespace() || ";&|(".contains(prev) => break,
+            (None, '\\') => {
+                code.push(' ');
+                if chars.next().is_some() {
+                    code.push(' ');
+                }
+            }
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                code.push(' ');
+            }
+            (Some('"'), '\\') => {
+                code.push(' ');
+                if chars.next().is_some() {
+                    code.push(' ');
+                }
+            }
+            (Some(q), _) if c == q => {
+                quote = None;
+                code.push(' ');
+            }
+            (Some('"'), _) if !blank_double => code.push(c),
+            (Some(_), _) => code.push(' '),
+            (None, _) => code.push(c),
+        }
+        prev = c;
+    }
+    code
+}
+
+fn words(code: &str) -> impl Iterator<Item = &str> {
+    code.split(|c: char| c.is_whitespace() || ";&|()`".contains(c))
+        .filter(|w| !w.is_empty())
+}
+
+/// End marker of a here-document started on `line`, as in `cat <<EOF`.
+/// A `<<` in quotes, a comment or arithmetic like `$((1 << 2))` is not one.
+fn heredoc_marker(line: &str) -> Option<String> {
+    // code_part keeps one char per char of the line, so positions match
+    let mut code: Vec<char> = code_part(line, true).chars().collect();
+    let mut i = 0;
+    while i + 1 < code.len() {
+        if code[i] == '(' && code[i + 1] == '(' {
+            let end = (i + 2..code.len() - 1)
+                .find(|&j| code[j] == ')' && code[j + 1] == ')')
+                .map_or(code.len(), |j| j + 2);
+            code[i..end].fill(' ');
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+    // `<<<` is a here-string
+    let start = (0..code.len().saturating_sub(1)).find(|&i| {
+        code[i] == '<' && code[i + 1] == '<' && code.get(i + 2) != Some(&'<') && (i == 0 || code[i - 1] != '<')
+    })?;
+    let rest: String = line.chars().skip(start + 2).collect();
+    let rest = rest.strip_prefix('-').unwrap_or(&rest).trim_start();
+    let marker: String = rest
+        .trim_start_matches(['\'', '"'])
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .collect();
+    (!marker.is_empty()).then_some(marker)
+}
+
+/// Check the maintainer script `text` called `script` (e.g. `postinst`) for
+/// common mistakes.
+pub fn check(text: &str, script: &str) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut problem = |line: usize, message: String| problems.push(Problem { line, message });
+
+    if !text.starts_with("#!") {
+        problem(1, "Missing shebang, e.g. #!/bin/sh".to_owned());
+    }
+    if let Some(line) = text.split('\n').position(|l| l.ends_with('\r')) {
+        problem(line + 1, "CRLF line endings, the script will not run".to_owned());
+    }
+
+    let mut open: Vec<(&'static str, usize)> = Vec::new();
+    let mut heredoc: Option<String> = None;
+    let mut uses_arg = None;
+    let mut mentioned = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if let Some(marker) = &heredoc {
+            if line.trim() == marker {
+                heredoc = None;
+            }
+            continue;
+        }
+        let code = code_part(line, true);
+        for word in words(&code) {
+            match word {
+                "if" => open.push(("if", i + 1)),
+                "case" => open.push(("case", i + 1)),
+                "fi" | "esac" => {
+                    let opener = if word == "fi" { "if" } else { "case" };
+                    match open.pop() {
+                        Some((w, _)) if w == opener => {}
+                        Some((w, at)) => problem(
+                            i + 1,
+                            format!("`{}` closes the `{}` opened on line {}", word, w, at),
+                        ),
+                        None => problem(i + 1, format!("`{}` without `{}`", word, opener)),
+                    }
+                }
+                _ => {}
+            }
+        }
+        let expanded = code_part(line, false);
+        if uses_arg.is_none() && (expanded.contains("$1") || expanded.contains("${1")) {
+            uses_arg = Some(i + 1);
+        }
+        mentioned.extend(
+            words(&expanded)
+                .flat_map(|w| w.split(['=', '*']))
+                .filter(|w| actions(script).contains(w))
+                .map(str::to_owned),
+        );
+        heredoc = heredoc_marker(line);
+    }
+    for (word, line) in open {
+        let closer = if word == "if" { "fi" } else { "esac" };
+        problem(line, format!("`{}` is never closed with `{}`", word, closer));
+    }
+    if let Some(line) = uses_arg {
+        let missing: Vec<&str> = actions(script)
+            .iter()
+            .filter(|a| !mentioned.iter().any(|m| m == *a))
+            .copied()
+            .collect();
+        if !missing.is_empty() {
+            problem(
+                line,
+                format!("Uses $1 but does not handle {}", missing.join(", ")),
+            );
+        }
+    }
+    problems.sort_by_key(|p| p.line);
+    problems
+}
+
+struct Palette {
+    text: Color32,
+    comment: Color32,
+    string: Color32,
+    keyword: Color32,
+    variable: Color32,
+}
+
+impl Palette {
+    fn new(visuals: &egui::Visuals) -> Self {
+        if visuals.dark_mode {
+            Self {
+                text: visuals.text_color(),
+                comment: Color32::from_gray(120),
+                string: Color32::from_rgb(152, 195, 121),
+                keyword: Color32::from_rgb(198, 120, 221),
+                variable: Color32::from_rgb(229, 192, 123),
+            }
+        } else {
+            Self {
+                text: visuals.text_color(),
+                comment: Color32::from_gray(130),
+                string: Color32::from_rgb(80, 130, 40),
+                keyword: Color32::from_rgb(150, 40, 170),
+                variable: Color32::from_rgb(170, 100, 0),
+            }
+        }
+    }
+}
+
+/// Length in bytes of the variable reference at the start of `rest`, which
+/// begins with `$`.
+fn variable_len(rest: &str) -> usize {
+    let after = &rest[1..];
+    if let Some(inner) = after.strip_prefix('{') {
+        return inner.find('}').map_or(rest.len(), |end| end + 3);
+    }
+    match after.chars().next() {
+        Some(c) if c.is_ascii_digit() || "@*#?$!-".contains(c) => 2,
+        Some(c) if c.is_alphabetic() || c == '_' => {
+            1 + after
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(after.len())
+        }
+        _ => 1,
+    }
+}
+
+/// Shell syntax highlighting for a script editor.
+pub fn highlight(style: &egui::Style, text: &str) -> LayoutJob {
+    let font_id = TextStyle::Monospace.resolve(style);
+    let palette = Palette::new(&style.visuals);
+    let mut job = LayoutJob::default();
+    let mut append = |part: &str, color: Color32| {
+        job.append(part, 0.0, TextFormat::simple(font_id.clone(), color));
+    };
+
+    let mut rest = text;
+    let mut word_start = true;
+    while let Some(c) = rest.chars().next() {
+        let (len, color) = match c {
+            '#' if word_start => (rest.find('\n').unwrap_or(rest.len()), palette.comment),
+            '\'' => (rest[1..].find('\'').map_or(rest.len(), |end| end + 2), palette.string),
+            '"' => {
+                let mut end = rest.len();
+                let mut escaped = false;
+                for (i, c) in rest.char_indices().skip(1) {
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => {
+                            end = i + 1;
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                (end, palette.string)
+            }
+            '$' => (variable_len(rest), palette.variable),
+            c if c.is_alphanumeric() || c == '_' => {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+                    .unwrap_or(rest.len());
+                let color = if word_start && KEYWORDS.contains(&&rest[..len]) {
+                    palette.keyword
+                } else {
+                    palette.text
+                };
+                (len, color)
+            }
+            c => (c.len_utf8(), palette.text),
+        };
+        append(&rest[..len], color);
+        word_start = c.is_whitespace() || ";&|()".contains(c);
+        rest = &rest[len..];
+    }
+    job
+}
+
+/// Script editor with highlighting and the checker's findings below it.
+pub fn editor(ui: &mut egui::Ui, text: &mut String, script: &str) {
+    let mut layouter = |ui: &egui::Ui, text: &str, wrap_width: f32| {
+        let mut job = highlight(ui.style(), text);
+        job.wrap.max_width = wrap_width;
+        ui.fonts(|f| f.layout_job(job))
+    };
+    ui.add(
+        egui::TextEdit::multiline(text)
+            .code_editor()
+            .layouter(&mut layouter),
+    );
+    for problem in check(text, script) {
+        ui.colored_label(
+            Color32::from_rgb(230, 150, 0),
+            format!("⚠ line {}: {}", problem.line, problem.message),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(text: &str, script: &str) -> Vec<(usize, String)> {
+        check(text, script).into_iter().map(|p| (p.line, p.message)).collect()
+    }
+
+    #[test]
+    fn clean_script() {
+        let text = "#!/bin/sh
+case \"$1\" in
+    configure)
+        if [ -x /usr/bin/app ]; then echo 'fi # not code'; fi
+        cat <<EOF >/etc/app.conf
+if unbalanced
+EOF
+        ;;
+esac
+";
+        assert!(problems(text, "postinst").is_empty(), "{:?}", problems(text, "postinst"));
+    }
+
+    #[test]
+    fn finds_common_mistakes() {
+        assert_eq!(
+            problems("echo hi\r\nif true; then\n", "postinst"),
+            [
+                (1, "Missing shebang, e.g. #!/bin/sh".to_owned()),
+                (1, "CRLF line endings, the script will not run".to_owned()),
+                (2, "`if` is never closed with `fi`".to_owned()),
+            ]
+        );
+        assert_eq!(
+            problems("#!/bin/sh\ncase x in\nfi\n", "postinst"),
+            [(3, "`fi` closes the `case` opened on line 2".to_owned())]
+        );
+    }
+
+    #[test]
+    fn unhandled_actions() {
+        let text = "#!/bin/sh\nif [ \"$1\" = remove ]; then\n    rm -rf /var/lib/app\nfi\n";
+        assert_eq!(problems(text, "prerm"), [(2, "Uses $1 but does not handle upgrade".to_owned())]);
+    }
+
+    #[test]
+    fn variable_lengths() {
+        assert_eq!(variable_len("$1 rest"), 2);
+        assert_eq!(variable_len("${PACKAGE}/x"), 10);
+        assert_eq!(variable_len("$HOME/x"), 5);
+        assert_eq!(variable_len("$ "), 1);
+        assert_eq!(heredoc_marker("cat <<-'END' > f").as_deref(), Some("END"));
+        assert_eq!(heredoc_marker("echo a << b"), Some("b".to_owned()));
+    }
+
+    #[test]
+    fn shifts_and_quotes_are_not_heredocs() {
+        assert_eq!(heredoc_marker("echo $((1 << 2))"), None);
+        assert_eq!(heredoc_marker("(( mask = 1 << 4 ))"), None);
+        assert_eq!(heredoc_marker("echo \"a << b\" 'c << d'"), None);
+        assert_eq!(heredoc_marker("true # see cat << EOF"), None);
+        assert_eq!(heredoc_marker("read x <<< \"$y\""), None);
+        assert_eq!(heredoc_marker("echo \"$((1 << 2))\" | cat <<\"EOF\"").as_deref(), Some("EOF"));
+        // the rest of the script is still checked
+        let text = "#!/bin/sh\necho $((1 << 2))\nfi\n";
+        assert_eq!(problems(text, "postinst"), [(3, "`fi` without `if`".to_owned())]);
+    }
+}
@@ -0,0 +1,342 @@
+use anyhow::{Context, Result};
+use goblin::elf::Elf;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+/// Settings for generating `Depends` entries from the shared libraries
+/// the binaries in the data tree are linked against.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShlibDeps {
+    pub enabled: bool,
+    /// opkg/dpkg `Packages` feed index
+    pub feed_index: Option<PathBuf>,
+    /// Debian style `Contents` index
+    pub contents_index: Option<PathBuf>,
+    /// plain text file with one `SONAME package` pair per line
+    pub soname_map: Option<PathBuf>,
+}
+
+/// Collect the `DT_NEEDED` entries of all ELF files in or below `inputs`.
+/// Libraries whose SONAME is provided by the inputs themselves are left out.
+pub fn needed_sonames<P: AsRef<Path>>(inputs: &[P]) -> Result<BTreeSet<String>> {
+    let mut needed = BTreeSet::new();
+    let mut provided = BTreeSet::new();
+    for entry in inputs.iter().flat_map(WalkDir::new) {
+        let entry = entry.context("Could not walk package inputs")?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        // most of the tree is not ELF, so look at the magic before reading it all
+        let mut magic = [0; 4];
+        let is_elf = File::open(entry.path())
+            .and_then(|mut f| f.read_exact(&mut magic))
+            .map(|()| magic == ELF_MAGIC)
+            .unwrap_or(false);
+        if !is_elf {
+            continue;
+        }
+        let buffer = fs::read(entry.path())
+            .context(format!("Could not read {}", entry.path().display()))?;
+        let elf = match Elf::parse(&buffer) {
+            Ok(elf) => elf,
+            Err(e) => {
+                warn!("Skipping malformed ELF file {}: {}", entry.path().display(), e);
+                continue;
+            }
+        };
+        if let Some(soname) = elf.soname {
+            provided.insert(soname.to_owned());
+        }
+        needed.extend(elf.libraries.iter().map(|l| l.to_string()));
+    }
+    Ok(needed.difference(&provided).cloned().collect())
+}
+
+/// Lookup table from SONAME to the package providing it.
+#[derive(Default)]
+pub struct Providers {
+    map: HashMap<String, String>,
+    packages: BTreeSet<String>,
+}
+
+impl Providers {
+    /// Load every source configured in `settings`. An explicit SONAME map
+    /// wins over the Contents index, which wins over the feed index.
+    pub fn load(settings: &ShlibDeps) -> Result<Self> {
+        let mut providers = Providers::default();
+        if let Some(path) = &settings.feed_index {
+            providers.read_feed_index(path)?;
+        }
+        if let Some(path) = &settings.contents_index {
+            providers.read_contents_index(path)?;
+        }
+        if let Some(path) = &settings.soname_map {
+            providers.read_soname_map(path)?;
+        }
+        Ok(providers)
+    }
+
+    fn read_feed_index(&mut self, path: &Path) -> Result<()> {
+        let text = fs::read_to_string(path)
+            .context(format!("Could not read feed index {}", path.display()))?;
+        self.add_feed_index(&text);
+        Ok(())
+    }
+
+    fn add_feed_index(&mut self, text: &str) {
+        for stanza in text.split("\n\n") {
+            let mut package = None;
+            let mut provides = Vec::new();
+            for line in stanza.lines() {
+                if let Some((key, value)) = line.split_once(':') {
+                    match key.trim() {
+                        "Package" => package = Some(value.trim().to_owned()),
+                        "Provides" => provides.extend(
+                            value
+                                .split(',')
+                                .map(|p| package_name(p).to_owned())
+                                .filter(|p| !p.is_empty()),
+                        ),
+                        _ => {}
+                    }
+                }
+            }
+            if let Some(package) = package {
+                for soname in provides {
+                    self.map.insert(soname, package.clone());
+                }
+                self.packages.insert(package);
+            }
+        }
+    }
+
+    fn read_contents_index(&mut self, path: &Path) -> Result<()> {
+        let text = fs::read_to_string(path)
+            .context(format!("Could not read Contents index {}", path.display()))?;
+        self.add_contents_index(&text);
+        Ok(())
+    }
+
+    fn add_contents_index(&mut self, text: &str) {
+        for line in text.lines() {
+            let Some((file, packages)) = line.trim_end().rsplit_once(char::is_whitespace) else {
+                continue;
+            };
+            let Some(soname) = Path::new(file.trim()).file_name().and_then(|f| f.to_str()) else {
+                continue;
+            };
+            if !soname.contains(".so") {
+                continue;
+            }
+            // "libs/libfoo1,libs/libfoo1-compat" -> "libfoo1"
+            let package = packages
+                .split(',')
+                .next()
+                .and_then(|p| p.rsplit('/').next())
+                .unwrap_or_default();
+            if !package.is_empty() {
+                self.map.insert(soname.to_owned(), package.to_owned());
+            }
+        }
+    }
+
+    fn read_soname_map(&mut self, path: &Path) -> Result<()> {
+        let text = fs::read_to_string(path)
+            .context(format!("Could not read SONAME map {}", path.display()))?;
+        self.add_soname_map(&text);
+        Ok(())
+    }
+
+    fn add_soname_map(&mut self, text: &str) {
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            if let (Some(soname), Some(package)) = (fields.next(), fields.next()) {
+                self.map.insert(soname.to_owned(), package.to_owned());
+            }
+        }
+    }
+
+    /// Find the package providing `soname`. Falls back to the Debian naming
+    /// convention (`libfoo.so.1` -> `libfoo1`) against the feed index.
+    pub fn resolve(&self, soname: &str) -> Option<&str> {
+        if let Some(package) = self.map.get(soname) {
+            return Some(package);
+        }
+        let guess = debian_package_name(soname)?;
+        self.packages.get(&guess).map(|p| p.as_str())
+    }
+}
+
+/// `libfoo.so.1` -> `libfoo1`, `libfoo-2.0.so.0` -> `libfoo-2.0-0`
+fn debian_package_name(soname: &str) -> Option<String> {
+    let (name, version) = soname.split_once(".so")?;
+    let version = version.trim_start_matches('.');
+    let name = name.to_lowercase();
+    Some(match version {
+        "" => name,
+        v if name.ends_with(|c: char| c.is_ascii_digit()) => format!("{}-{}", name, v),
+        v => format!("{}{}", name, v),
+    })
+}
+
+/// Strip version constraints and architecture qualifiers from a
+/// relationship entry: `libfoo1 (>= 1.2)` -> `libfoo1`
+fn package_name(entry: &str) -> &str {
+    entry
+        .trim()
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ':')
+        .next()
+        .unwrap_or_default()
+}
+
+/// Merge `deps` into the `Depends` field of `control`, keeping existing
+/// entries (and their version constraints) untouched. A field folded over
+/// continuation lines is rewritten on one line.
+pub fn merge_depends(control: &str, deps: &BTreeSet<String>) -> String {
+    let mut lines: Vec<String> = control.lines().map(|l| l.to_owned()).collect();
+    let depends_line = lines.iter().position(|l| {
+        l.split_once(':')
+            .map(|(key, _)| !l.starts_with([' ', '\t']) && key.trim().eq_ignore_ascii_case("Depends"))
+            .unwrap_or(false)
+    });
+    let mut entries: Vec<String> = Vec::new();
+    let mut field_lines = 0;
+    if let Some(i) = depends_line {
+        let continuation = lines[i + 1..]
+            .iter()
+            .take_while(|l| l.starts_with([' ', '\t']))
+            .count();
+        field_lines = 1 + continuation;
+        let value = lines[i..i + field_lines]
+            .iter()
+            .enumerate()
+            .map(|(n, l)| if n == 0 { l.split_once(':').map(|(_, v)| v).unwrap_or_default() } else { l })
+            .collect::<Vec<_>>()
+            .join(" ");
+        entries = value
+            .split(',')
+            .map(|e| e.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|e| !e.is_empty())
+            .collect();
+    }
+    for dep in deps {
+        if !entries.iter().any(|e| package_name(e) == dep) {
+            entries.push(dep.clone());
+        }
+    }
+    if entries.is_empty() {
+        return control.to_owned();
+    }
+    let depends = format!("Depends: {}", entries.join(", "));
+    match depends_line {
+        Some(i) => {
+            lines.splice(i..i + field_lines, [depends]);
+        }
+        None => lines.push(depends),
+    }
+    let mut merged = lines.join("\n");
+    if control.ends_with('\n') {
+        merged.push('\n');
+    }
+    merged
+}
+
+/// Resolve the shared library dependencies of `inputs` (files or folders)
+/// and merge them into `control`.
+pub fn apply<P: AsRef<Path>>(settings: &ShlibDeps, inputs: &[P], control: &str) -> Result<String> {
+    let providers = Providers::load(settings)?;
+    let mut deps = BTreeSet::new();
+    for soname in needed_sonames(inputs)? {
+        match providers.resolve(&soname) {
+            Some(package) => {
+                info!("{} is provided by {}", soname, package);
+                deps.insert(package.to_owned());
+            }
+            None => warn!("No package found providing {}", soname),
+        }
+    }
+    Ok(merge_depends(control, &deps))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED_INDEX: &str = "Package: libfoo1
+Version: 1.0
+Provides: libfoo.so.1
+
+Package: libbar-2.0-0
+Version: 2.0
+
+Package: libssl3
+Provides: libssl.so.3, libcrypto.so.3 (= 3.0)
+";
+
+    const CONTENTS_INDEX: &str = "usr/lib/libz.so.1                libs/zlib1g,libs/zlib1g-compat
+usr/share/doc/zlib/README        doc/zlib1g
+usr/lib/libfoo.so.1              libs/libfoo-contents
+";
+
+    fn deps(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn debian_names() {
+        assert_eq!(debian_package_name("libfoo.so.1").as_deref(), Some("libfoo1"));
+        assert_eq!(debian_package_name("libfoo-2.0.so.0").as_deref(), Some("libfoo-2.0-0"));
+        assert_eq!(debian_package_name("libGL.so").as_deref(), Some("libgl"));
+        assert_eq!(debian_package_name("ld-linux"), None);
+    }
+
+    #[test]
+    fn providers_from_indexes() {
+        let mut providers = Providers::default();
+        providers.add_feed_index(FEED_INDEX);
+        assert_eq!(providers.resolve("libfoo.so.1"), Some("libfoo1"));
+        assert_eq!(providers.resolve("libcrypto.so.3"), Some("libssl3"));
+        // not provided explicitly, found by the naming convention
+        assert_eq!(providers.resolve("libbar-2.0.so.0"), Some("libbar-2.0-0"));
+        assert_eq!(providers.resolve("libz.so.1"), None);
+
+        providers.add_contents_index(CONTENTS_INDEX);
+        assert_eq!(providers.resolve("libz.so.1"), Some("zlib1g"));
+        assert_eq!(providers.resolve("libfoo.so.1"), Some("libfoo-contents"));
+
+        providers.add_soname_map("libfoo.so.1 libfoo-mapped # comment\n# libz.so.1 nothing\n");
+        assert_eq!(providers.resolve("libfoo.so.1"), Some("libfoo-mapped"));
+        assert_eq!(providers.resolve("libz.so.1"), Some("zlib1g"));
+    }
+
+    #[test]
+    fn merge_adds_missing_depends() {
+        let control = "Package: app\nDepends: libc, libfoo1 (>= 1.2)\n";
+        assert_eq!(
+            merge_depends(control, &deps(&["libfoo1", "libz1"])),
+            "Package: app\nDepends: libc, libfoo1 (>= 1.2), libz1\n"
+        );
+        assert_eq!(merge_depends("Package: app", &deps(&["libz1"])), "Package: app\nDepends: libz1");
+        assert_eq!(merge_depends("Package: app\n", &deps(&[])), "Package: app\n");
+    }
+
+    #[test]
+    fn merge_folds_continuation_lines() {
+        let control = "Package: app\nDepends: libc,\n libfoo1 (>= 1.2),\n  libbar\nDescription: x\n";
+        assert_eq!(
+            merge_depends(control, &deps(&["libbar", "libz1"])),
+            "Package: app\nDepends: libc, libfoo1 (>= 1.2), libbar, libz1\nDescription: x\n"
+        );
+    }
+}
@@ -0,0 +1,357 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::shell;
+
+/// Snippets that come with the builder. User snippet files use the same
+/// format: a header of `# key: value` lines, then the script body.
+/// Parameters are written as `@NAME@` in the body, outside of quotes; the
+/// values are quoted for the shell when they are filled in.
+const BUILTIN: &[&str] = &[
+    "# name: initd-service
+# description: Enable and start an init.d service
+# script: postinst
+# when: configure
+# param: SERVICE
+# enabling works on an offline root file system too, starting does not
+if [ -x \"$IPKG_INSTROOT\"/etc/init.d/@SERVICE@ ]; then
+    \"$IPKG_INSTROOT\"/etc/init.d/@SERVICE@ enable
+fi
+if [ -z \"$IPKG_INSTROOT\" ] && [ -x /etc/init.d/@SERVICE@ ]; then
+    /etc/init.d/@SERVICE@ restart
+fi
+",
+    "# name: systemd-service
+# description: Enable and start a systemd unit
+# script: postinst
+# when: configure
+# param: UNIT
+# enabling works on an offline root file system too, starting does not
+systemctl ${IPKG_INSTROOT:+--root=\"$IPKG_INSTROOT\"} enable @UNIT@
+if [ -z \"$IPKG_INSTROOT\" ] && [ -d /run/systemd/system ]; then
+    systemctl daemon-reload
+    systemctl restart @UNIT@
+fi
+",
+    "# name: ldconfig
+# description: Refresh the shared library cache
+# script: postinst
+# when: configure
+if command -v ldconfig >/dev/null; then
+    ldconfig
+fi
+",
+    "# name: system-user
+# description: Create a system user and group unless it exists
+# script: postinst
+# when: configure
+# param: USER
+# param: HOME=/nonexistent
+# param: SHELL=/bin/false
+if ! id -u @USER@ >/dev/null 2>&1; then
+    if command -v useradd >/dev/null; then
+        useradd --system --user-group --home-dir @HOME@ --shell @SHELL@ @USER@
+    else
+        addgroup -S @USER@
+        adduser -S -D -H -h @HOME@ -s @SHELL@ -G @USER@ @USER@
+    fi
+fi
+",
+    "# name: permissions
+# description: Set the owner and mode of an installed path
+# script: postinst
+# when: configure
+# param: TARGET
+# param: OWNER=root:root
+# param: MODE=0644
+chown @OWNER@ @TARGET@
+chmod @MODE@ @TARGET@
+",
+    "# name: udev-reload
+# description: Reload udev rules and replay device events
+# script: postinst
+# when: configure
+if command -v udevadm >/dev/null; then
+    udevadm control --reload-rules
+    udevadm trigger
+fi
+",
+];
+
+/// A parameterized piece of a maintainer script.
+#[derive(Clone)]
+pub struct Snippet {
+    pub name: String,
+    pub description: String,
+    /// script the snippet belongs in, e.g. `postinst`
+    pub script: String,
+    /// values of `$1` the snippet runs for
+    pub when: Vec<String>,
+    /// parameter names with their default, empty if it must be given
+    pub params: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// `value` as one shell word. Plain words are left alone.
+fn quote(value: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_./:@%+=,-".contains(c);
+    if value.chars().all(plain) {
+        value.to_owned()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+impl Snippet {
+    fn parse(text: &str, fallback_name: &str) -> Result<Self> {
+        let mut snippet = Snippet {
+            name: fallback_name.to_owned(),
+            description: String::new(),
+            script: "postinst".to_owned(),
+            when: Vec::new(),
+            params: Vec::new(),
+            body: String::new(),
+        };
+        let mut lines = text.lines().peekable();
+        while let Some(line) = lines.peek() {
+            let Some((key, value)) = line.strip_prefix('#').and_then(|l| l.split_once(':')) else {
+                break;
+            };
+            let value = value.trim().to_owned();
+            match key.trim() {
+                "name" => snippet.name = value,
+                "description" => snippet.description = value,
+                "script" => snippet.script = value,
+                "when" => snippet.when = value.split_whitespace().map(str::to_owned).collect(),
+                "param" => {
+                    let (name, default) = value.split_once('=').unwrap_or((&value, ""));
+                    snippet.params.push((name.trim().to_owned(), default.trim().to_owned()));
+                }
+                _ => break,
+            }
+            lines.next();
+        }
+        if shell::actions(&snippet.script).is_empty() {
+            bail!("Unknown script {} in snippet {}", snippet.script, snippet.name);
+        }
+        if snippet.when.is_empty() {
+            snippet.when.push(shell::actions(&snippet.script)[0].to_owned());
+        }
+        snippet.body = lines.map(|l| format!("{}\n", l)).collect();
+        Ok(snippet)
+    }
+
+    /// The body with the given parameters filled in and quoted. Parameters
+    /// that are neither given nor have a default stay as `@NAME@`.
+    pub fn fill(&self, params: &BTreeMap<String, String>) -> String {
+        let mut body = self.body.clone();
+        for (name, default) in &self.params {
+            let value = params.get(name).filter(|v| !v.is_empty()).unwrap_or(default);
+            if !value.is_empty() {
+                body = body.replace(&format!("@{}@", name), &quote(value));
+            }
+        }
+        body
+    }
+
+    /// Like [`Snippet::fill`], but every parameter must have a value.
+    pub fn render(&self, params: &BTreeMap<String, String>) -> Result<String> {
+        for (name, default) in &self.params {
+            if default.is_empty() && params.get(name).is_none_or(|v| v.is_empty()) {
+                bail!("Snippet {} needs a value for {}", self.name, name);
+            }
+        }
+        Ok(self.fill(params))
+    }
+}
+
+/// Snippets used by a project and where to find more of them.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Snippets {
+    /// folders with `*.sh` snippet files
+    pub dirs: Vec<PathBuf>,
+    pub uses: Vec<SnippetUse>,
+}
+
+/// A snippet referenced from the project, with its parameter values.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnippetUse {
+    pub name: String,
+    pub params: BTreeMap<String, String>,
+}
+
+fn load_dir(dir: &Path, snippets: &mut Vec<Snippet>) -> Result<()> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .context(format!("Could not read snippet folder {}", dir.display()))?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    files.retain(|f| f.extension().is_some_and(|e| e == "sh"));
+    files.sort();
+    for file in files {
+        let text = fs::read_to_string(&file)
+            .context(format!("Could not read snippet {}", file.display()))?;
+        let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+        let snippet = Snippet::parse(&text, &stem).context(format!("In {}", file.display()))?;
+        // a user snippet replaces a built-in one of the same name
+        snippets.retain(|s| s.name != snippet.name);
+        snippets.push(snippet);
+    }
+    Ok(())
+}
+
+/// The built-in snippets followed by those from `dirs`.
+pub fn library(dirs: &[PathBuf]) -> Result<Vec<Snippet>> {
+    let mut snippets = BUILTIN
+        .iter()
+        .map(|text| Snippet::parse(text, ""))
+        .collect::<Result<Vec<_>>>()?;
+    for dir in dirs {
+        load_dir(dir, &mut snippets)?;
+    }
+    Ok(snippets)
+}
+
+fn indent(body: &str, prefix: &str) -> String {
+    body.lines()
+        .map(|l| if l.is_empty() { "\n".to_owned() } else { format!("{}{}\n", prefix, l) })
+        .collect()
+}
+
+/// `case "$1"` block running the project's snippets for `script`, or
+/// `None` if none of them belong there.
+pub fn compose(script: &str, library: &[Snippet], uses: &[SnippetUse]) -> Result<Option<String>> {
+    let mut arms: BTreeMap<usize, String> = BTreeMap::new();
+    let actions = shell::actions(script);
+    for used in uses {
+        let snippet = library
+            .iter()
+            .find(|s| s.name == used.name)
+            .context(format!("Unknown snippet {}", used.name))?;
+        if snippet.script != script {
+            continue;
+        }
+        let body = snippet.render(&used.params)?;
+        for when in &snippet.when {
+            let Some(i) = actions.iter().position(|a| a == when) else {
+                bail!("Snippet {} runs for {}, which {} is never called with", snippet.name, when, script);
+            };
+            let arm = arms.entry(i).or_default();
+            arm.push_str(&format!("        # {}\n", snippet.name));
+            arm.push_str(&indent(&body, "        "));
+        }
+    }
+    if arms.is_empty() {
+        return Ok(None);
+    }
+    let mut block = "case \"$1\" in\n".to_owned();
+    for (i, arm) in arms {
+        block.push_str(&format!("    {})\n{}        ;;\n", actions[i], arm));
+    }
+    block.push_str("esac\n");
+    Ok(Some(block))
+}
+
+/// Combine the snippet block with the script written by the user. The block
+/// runs after the user script: it goes before the script's final `exit 0`
+/// if it has one, at the end otherwise. Without a user script the block
+/// makes up the whole script.
+pub fn combine(block: &str, script: Option<&str>) -> String {
+    let Some(script) = script else {
+        return format!("#!/bin/sh\nset -e\n\n{}\nexit 0\n", block);
+    };
+    let body = script.trim_end();
+    let last_line = body.rsplit('\n').next().unwrap_or_default();
+    if last_line.trim() == "exit 0" {
+        let before = &body[..body.len() - last_line.len()];
+        format!("{}\n{}\n{}\n", before.trim_end(), block, last_line)
+    } else {
+        format!("{}\n\n{}", body, block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::scratch;
+
+    fn params(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    fn builtin(name: &str) -> Snippet {
+        library(&[]).unwrap().into_iter().find(|s| s.name == name).unwrap()
+    }
+
+    #[test]
+    fn parses_the_header() {
+        let snippet = Snippet::parse("# name: greet\n# script: prerm\n# param: WHO=world\necho @WHO@\n", "file").unwrap();
+        assert_eq!(snippet.name, "greet");
+        assert_eq!(snippet.when, ["remove"]);
+        assert_eq!(snippet.params, [("WHO".to_owned(), "world".to_owned())]);
+        assert_eq!(snippet.body, "echo @WHO@\n");
+        assert!(Snippet::parse("# script: postinstall\n", "file").is_err());
+    }
+
+    #[test]
+    fn user_snippets_replace_builtin_ones() {
+        let dir = scratch("snippets");
+        fs::write(dir.join("ldconfig.sh"), "# description: Our own\n/sbin/ldconfig -X\n").unwrap();
+        fs::write(dir.join("notes.txt"), "not a snippet").unwrap();
+        let library = library(&[dir]).unwrap();
+        assert_eq!(library.iter().filter(|s| s.name == "ldconfig").count(), 1);
+        let ours = library.last().unwrap();
+        assert_eq!((ours.name.as_str(), ours.body.as_str()), ("ldconfig", "/sbin/ldconfig -X\n"));
+        assert_eq!(library.len(), BUILTIN.len());
+    }
+
+    #[test]
+    fn values_are_quoted() {
+        let snippet = builtin("permissions");
+        let body = snippet.render(&params(&[("TARGET", "/srv/my files"), ("MODE", "0600")])).unwrap();
+        assert_eq!(body, "chown root:root '/srv/my files'\nchmod 0600 '/srv/my files'\n");
+        assert_eq!(quote("it's"), "'it'\\''s'");
+        assert!(snippet.render(&params(&[])).is_err());
+    }
+
+    #[test]
+    fn composes_a_case_block() {
+        let uses = [SnippetUse {
+            name: "systemd-service".to_owned(),
+            params: params(&[("UNIT", "app.service")]),
+        }];
+        let library = library(&[]).unwrap();
+        assert_eq!(compose("prerm", &library, &uses).unwrap(), None);
+        let block = compose("postinst", &library, &uses).unwrap().unwrap();
+        assert!(block.starts_with("case \"$1\" in\n    configure)\n        # systemd-service\n"), "{}", block);
+        // enabling is not skipped on an offline root file system
+        assert!(
+            block.contains(
+                "        systemctl ${IPKG_INSTROOT:+--root=\"$IPKG_INSTROOT\"} enable app.service
+        if [ -z \"$IPKG_INSTROOT\" ] && [ -d /run/systemd/system ]; then"
+            ),
+            "{}",
+            block
+        );
+    }
+
+    #[test]
+    fn block_runs_after_the_user_script() {
+        let block = "case \"$1\" in\nesac\n";
+        assert_eq!(
+            combine(block, Some("#!/bin/sh\necho setup\nexit 0\n\n")),
+            "#!/bin/sh\necho setup\ncase \"$1\" in\nesac\n\nexit 0\n"
+        );
+        assert_eq!(
+            combine(block, Some("#!/bin/sh\necho setup\n")),
+            "#!/bin/sh\necho setup\n\ncase \"$1\" in\nesac\n"
+        );
+        assert_eq!(combine(block, None), "#!/bin/sh\nset -e\n\ncase \"$1\" in\nesac\n\nexit 0\n");
+    }
+}
@@ -0,0 +1,125 @@
+use anyhow::{bail, Context, Result};
+use glob::{MatchOptions, Pattern};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+/// `*` and `?` stay within one path component, as in `FILES`.
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// One output package carved out of the shared data root.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SplitPackage {
+    pub name: String,
+    /// control fields set on top of the main control file
+    pub control: String,
+    /// whitespace separated `FILES`-style glob patterns, e.g.
+    /// `/usr/include /usr/lib/*.so /usr/lib/pkgconfig`
+    pub files: String,
+}
+
+impl SplitPackage {
+    fn patterns(&self) -> Result<Vec<Pattern>> {
+        self.files
+            .split_whitespace()
+            .map(|p| {
+                Pattern::new(&format!("/{}", p.trim_start_matches('/')))
+                    .context(format!("Invalid pattern {} in package {}", p, self.name))
+            })
+            .collect()
+    }
+}
+
+/// A pattern claims a file if it matches the file itself or one of the
+/// directories containing it.
+fn claims(patterns: &[Pattern], path: &Path) -> bool {
+    path.ancestors()
+        .take_while(|a| *a != Path::new("/"))
+        .any(|a| patterns.iter().any(|p| p.matches_path_with(a, MATCH_OPTIONS)))
+}
+
+/// Assign every path (relative to the package root) to the first package
+/// whose patterns match it. Returns the paths for each package, in the
+/// order of `packages`. Unclaimed paths are reported and left out.
+pub fn assign(paths: Vec<PathBuf>, packages: &[SplitPackage]) -> Result<Vec<Vec<PathBuf>>> {
+    let mut names = HashSet::new();
+    for package in packages {
+        let name = package.name.trim();
+        if name.is_empty() {
+            bail!("A split package has no name");
+        }
+        if !names.insert(name) {
+            bail!("Split package {} is listed twice", name);
+        }
+    }
+    let patterns = packages
+        .iter()
+        .map(|p| p.patterns())
+        .collect::<Result<Vec<_>>>()?;
+    let mut assigned = vec![Vec::new(); packages.len()];
+    for relative in paths {
+        let absolute = Path::new("/").join(&relative);
+        match patterns.iter().position(|p| claims(p, &absolute)) {
+            Some(i) => assigned[i].push(relative),
+            None => warn!("{} is not claimed by any package", absolute.display()),
+        }
+    }
+    Ok(assigned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, files: &str) -> SplitPackage {
+        SplitPackage {
+            name: name.to_owned(),
+            files: files.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn first_matching_package_wins() {
+        let packages = [
+            package("app-dev", "/usr/include /usr/lib/*.so"),
+            package("app", "/usr"),
+        ];
+        let assigned = assign(
+            paths(&["usr/include/app/app.h", "usr/lib/libapp.so", "usr/lib/libapp.so.1", "usr/bin/app", "etc/app.conf"]),
+            &packages,
+        )
+        .unwrap();
+        assert_eq!(assigned[0], paths(&["usr/include/app/app.h", "usr/lib/libapp.so"]));
+        // etc/app.conf is claimed by nobody and left out
+        assert_eq!(assigned[1], paths(&["usr/lib/libapp.so.1", "usr/bin/app"]));
+    }
+
+    #[test]
+    fn star_stays_in_one_directory() {
+        let packages = [package("app-dev", "/usr/lib/*.so"), package("app", "/usr")];
+        let assigned = assign(paths(&["usr/lib/plugins/extra.so"]), &packages).unwrap();
+        assert!(assigned[0].is_empty());
+        assert_eq!(assigned[1], paths(&["usr/lib/plugins/extra.so"]));
+    }
+
+    #[test]
+    fn names_must_be_set_and_unique() {
+        let error = assign(Vec::new(), &[package(" ", "/usr")]).unwrap_err();
+        assert!(error.to_string().contains("no name"), "{}", error);
+        let error = assign(Vec::new(), &[package("app", "/usr"), package("app", "/etc")]).unwrap_err();
+        assert!(error.to_string().contains("listed twice"), "{}", error);
+    }
+}
@@ -0,0 +1,12 @@
+//! Helpers shared by the unit tests, like `tests/common` for the
+//! integration tests.
+
+use std::{env, fs, path::PathBuf, process};
+
+/// Empty scratch folder for one test.
+pub fn scratch(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("ipkbuilder-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
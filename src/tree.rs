@@ -0,0 +1,171 @@
+use anyhow::{Context, Result};
+use eframe::egui::{self, RichText};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::exclude;
+
+/// Per-entry choices made in the data tree browser, keyed by the path
+/// relative to the data root (`usr/bin/app`).
+pub type Overrides = BTreeMap<String, FileOverride>;
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileOverride {
+    /// `None` leaves the decision to the exclude patterns
+    pub include: Option<bool>,
+    /// octal, e.g. `0755`
+    pub mode: String,
+    /// `user:group`
+    pub owner: String,
+    pub conffile: bool,
+}
+
+impl FileOverride {
+    pub fn parsed_mode(&self) -> Result<Option<u32>> {
+        if self.mode.trim().is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(
+            u32::from_str_radix(self.mode.trim(), 8)
+                .context(format!("Invalid mode {}", self.mode))?,
+        ))
+    }
+
+    pub fn parsed_owner(&self) -> Option<(String, String)> {
+        let owner = self.owner.trim();
+        if owner.is_empty() {
+            return None;
+        }
+        let (user, group) = owner.split_once(':').unwrap_or((owner, owner));
+        Some((user.to_owned(), group.to_owned()))
+    }
+}
+
+/// Key of `path` in [`Overrides`].
+pub fn key(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(unix)]
+fn mode_of(meta: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn mode_of(meta: &fs::Metadata) -> u32 {
+    if meta.is_dir() {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+/// A data root entry as shown in the browser.
+pub struct TreeNode {
+    pub name: String,
+    pub key: String,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    pub size: u64,
+    pub mode: u32,
+    /// excluded by the project patterns or `.ipkignore`
+    pub pattern_excluded: bool,
+    pub children: Vec<TreeNode>,
+}
+
+/// Read the data root for the browser. Excluded folders are listed too so
+/// entries can be brought back in.
+pub fn scan(root: &Path, patterns: &str) -> Result<Vec<TreeNode>> {
+    let matcher = exclude::matcher(root, patterns)?;
+    scan_dir(root, root, &matcher, false)
+}
+
+fn scan_dir(
+    root: &Path,
+    dir: &Path,
+    matcher: &ignore::gitignore::Gitignore,
+    parent_excluded: bool,
+) -> Result<Vec<TreeNode>> {
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .context(format!("Could not read {}", dir.display()))?
+        .collect::<Result<_, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    let mut nodes = Vec::new();
+    for entry in entries {
+        let path = entry.path();
+        let meta = fs::symlink_metadata(&path).context(format!("Could not read {}", path.display()))?;
+        let is_dir = meta.is_dir();
+        let pattern_excluded = parent_excluded || matcher.matched(&path, is_dir).is_ignore();
+        nodes.push(TreeNode {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            key: key(path.strip_prefix(root)?),
+            is_dir,
+            is_symlink: meta.file_type().is_symlink(),
+            size: meta.len(),
+            mode: mode_of(&meta),
+            pattern_excluded,
+            children: if is_dir {
+                scan_dir(root, &path, matcher, pattern_excluded)?
+            } else {
+                Vec::new()
+            },
+        });
+    }
+    Ok(nodes)
+}
+
+/// Show `nodes` as a collapsible tree with editors for the overrides.
+pub fn show(ui: &mut egui::Ui, nodes: &[TreeNode], overrides: &mut Overrides) {
+    for node in nodes {
+        if node.is_dir {
+            egui::CollapsingHeader::new(RichText::new(format!("{}/", node.name)).monospace())
+                .id_source(&node.key)
+                .show(ui, |ui| {
+                    show_row(ui, node, overrides);
+                    show(ui, &node.children, overrides);
+                });
+        } else {
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(&node.name).monospace());
+                show_row(ui, node, overrides);
+            });
+        }
+    }
+}
+
+fn show_row(ui: &mut egui::Ui, node: &TreeNode, overrides: &mut Overrides) {
+    let mut entry = overrides.get(&node.key).cloned().unwrap_or_default();
+    ui.horizontal(|ui| {
+        let mut included = entry.include.unwrap_or(!node.pattern_excluded);
+        if ui.checkbox(&mut included, "include").changed() {
+            // back to the pattern's decision if that is what was picked
+            entry.include = (included == node.pattern_excluded).then_some(included);
+        }
+        let kind = match (node.is_dir, node.is_symlink) {
+            (true, _) => "dir",
+            (_, true) => "link",
+            _ => "file",
+        };
+        ui.label(format!("{} {} bytes {:04o}", kind, node.size, node.mode));
+        ui.add(
+            egui::TextEdit::singleline(&mut entry.mode)
+                .hint_text("mode")
+                .desired_width(40.),
+        );
+        ui.add(
+            egui::TextEdit::singleline(&mut entry.owner)
+                .hint_text("user:group")
+                .desired_width(90.),
+        );
+        if !node.is_dir {
+            ui.checkbox(&mut entry.conffile, "conffile");
+        }
+    });
+    if entry == FileOverride::default() {
+        overrides.remove(&node.key);
+    } else {
+        overrides.insert(node.key.clone(), entry);
+    }
+}
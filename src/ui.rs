@@ -1,13 +1,34 @@
-use anyhow::anyhow;
+use anyhow::{anyhow, bail, Context};
 use anyhow::{Result, Error};
 use eframe::{
     egui::{self, RichText},
     epaint::{Color32, Vec2},
 };
-use std::path::PathBuf;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
+};
 
-use crate::make_package;
+use crate::{
+    console,
+    git::GitVersion,
+    data_tree, exclude, feed, form, make_packages, manifest, preview_variables, progress::Progress, project,
+    service::{InitSystem, Service},
+    session::{Session, SESSION_KEY},
+    shell,
+    snippets::{self, Snippet, SnippetUse, Snippets},
+    shlibdeps::ShlibDeps,
+    split::SplitPackage,
+    tree::{self, Overrides, TreeNode},
+    validate::{self, Section},
+    vars::Variables,
+    watch::{Watcher, DEFAULT_DEBOUNCE},
+};
 
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct FileOrPath {
     pub enabled: bool,
     pub file_or_text: ScriptSource,
@@ -32,21 +53,101 @@ Depends: other_package"
     }
 }
 
-#[derive(PartialEq, Default)]
+#[derive(PartialEq, Default, Serialize, Deserialize)]
 pub enum ScriptSource {
     #[default]
     FromPath,
     FromTextfield,
 }
 
+/// An error with the chain of its causes folded away below it.
+fn error_view(ui: &mut egui::Ui, e: &Error) {
+    let causes: Vec<String> = e.chain().skip(1).map(|c| c.to_string()).collect();
+    if causes.is_empty() {
+        ui.colored_label(Color32::RED, e.to_string());
+        return;
+    }
+    egui::CollapsingHeader::new(RichText::new(e.to_string()).color(Color32::RED))
+        .id_source("error_chain")
+        .show(ui, |ui| {
+            for (i, cause) in causes.iter().enumerate() {
+                ui.label(format!("{}: {}", i + 1, cause));
+            }
+            if ui.button("Copy").clicked() {
+                ui.output_mut(|o| o.copied_text = format!("{:?}", e));
+            }
+        });
+}
+
+/// A build running on a worker thread.
+pub struct BuildJob {
+    progress: Progress,
+    handle: JoinHandle<Result<String>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct IpkBuilder {
     pub control_file: FileOrPath,
+    /// edit the control text through the form instead of the raw text view
+    pub control_form: bool,
+    /// choices of the Architecture dropdown, one per line
+    pub architectures: String,
     pub debian_binary: FileOrPath,
     pub postinst: FileOrPath,
     pub preinst: FileOrPath,
     pub prerm: FileOrPath,
+    /// host files installed to arbitrary target paths
+    pub manifest: FileOrPath,
     pub data_path: Option<String>,
+    /// gitignore-style patterns of data root entries to leave out
+    pub exclude: String,
+    /// include, mode, owner and conffile choices from the data tree browser
+    pub overrides: Overrides,
     pub output_path: Option<String>,
+    /// package file name, may use `${PACKAGE}`, `${VERSION}` and `${ARCH}`
+    pub output_name: String,
+    /// write a `Packages` index of the output folder after each build
+    pub index_feed: bool,
+    /// write SPDX and CycloneDX documents next to each package
+    pub sbom: bool,
+    pub shlibdeps: ShlibDeps,
+    pub split_packages: Vec<SplitPackage>,
+    pub snippets: Snippets,
+    pub service: Service,
+    pub variables: Variables,
+    pub git_version: GitVersion,
+    /// project file the state was loaded from or last saved to
+    #[serde(skip)]
+    pub project_path: Option<PathBuf>,
+    #[serde(skip)]
+    pub session: Session,
+    #[serde(skip)]
+    pub data_tree: Option<Result<Vec<TreeNode>, Error>>,
+    /// loaded on first use and on "Reload"
+    #[serde(skip)]
+    pub snippet_library: Option<Result<Vec<Snippet>, Error>>,
+    /// index into the library of the snippet picked for adding
+    #[serde(skip)]
+    pub snippet_pick: usize,
+    #[serde(skip)]
+    pub dry_run: Option<Result<String, Error>>,
+    #[serde(skip)]
+    pub variables_preview: Option<Result<String, Error>>,
+    /// set while watch mode is on
+    #[serde(skip)]
+    pub watcher: Option<Watcher>,
+    #[serde(skip)]
+    pub build: Option<BuildJob>,
+    /// group to scroll to, set by clicking a problem
+    #[serde(skip)]
+    pub jump_to: Option<Section>,
+    /// path dropped onto the window, taken by the group under the pointer
+    #[serde(skip)]
+    pub dropped: Option<PathBuf>,
+    #[serde(skip)]
+    pub console: console::View,
+    #[serde(skip)]
     pub success_or_not: Result<String, Error>,
 }
 
@@ -54,6 +155,8 @@ impl Default for IpkBuilder {
     fn default() -> Self {
         Self {
             control_file: Default::default(),
+            control_form: false,
+            architectures: form::DEFAULT_ARCHITECTURES.to_owned(),
             debian_binary: FileOrPath {
                 enabled: true,
                 from_textbox: "2.0".to_owned(),
@@ -72,397 +175,1130 @@ impl Default for IpkBuilder {
                 from_textbox: "#!/bin/bash\n".to_owned(),
                 ..Default::default()
             },
+            manifest: FileOrPath {
+                from_textbox: manifest::EXAMPLE.to_owned(),
+                file_or_text: ScriptSource::FromTextfield,
+                ..Default::default()
+            },
             data_path: Default::default(),
+            exclude: exclude::DEFAULT_PATTERNS.to_owned(),
+            overrides: Default::default(),
             output_path: Default::default(),
+            output_name: "outpackage.ipk".to_owned(),
+            index_feed: false,
+            sbom: false,
+            shlibdeps: Default::default(),
+            split_packages: Default::default(),
+            snippets: Default::default(),
+            service: Default::default(),
+            variables: Default::default(),
+            git_version: Default::default(),
+            data_tree: None,
+            snippet_library: None,
+            snippet_pick: 0,
+            dry_run: None,
+            variables_preview: None,
+            watcher: None,
+            build: None,
+            jump_to: None,
+            dropped: None,
+            console: Default::default(),
+            project_path: Default::default(),
+            session: Default::default(),
             success_or_not: Err(anyhow!(" ")),
         }
     }
 }
 
 impl IpkBuilder {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Self {
-            ..Default::default()
+    /// Restore the last session from the eframe storage, if there is one.
+    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        // the console keeps records from the start, not only once it is open
+        console::set_level(console::View::default().level);
+        let Some(storage) = cc.storage else {
+            return Default::default();
+        };
+        let mut app: Self = eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+        app.session = eframe::get_value(storage, SESSION_KEY).unwrap_or_default();
+        app.project_path = app.session.project_path.clone();
+        app
+    }
+
+    /// Replace the builder state with a project file, keeping the session.
+    /// Refused while a build is running, it would lose track of it.
+    pub fn open_project(&mut self, path: &Path) {
+        if self.build.is_some() {
+            self.success_or_not = Err(anyhow!(
+                "Not opening {} while a build is running",
+                path.display()
+            ));
+            return;
+        }
+        match project::load(path) {
+            Ok(loaded) => {
+                let session = std::mem::take(&mut self.session);
+                *self = loaded;
+                self.session = session;
+                self.session.add_recent(path);
+            }
+            Err(e) => self.success_or_not = Err(e),
+        }
+    }
+
+    fn save_project(&mut self, path: PathBuf) {
+        match project::save(self, &path) {
+            Ok(()) => {
+                self.success_or_not = Ok(format!("Saved {}", path.display()));
+                self.session.add_recent(&path);
+                self.project_path = Some(path);
+            }
+            Err(e) => self.success_or_not = Err(e),
+        }
+    }
+
+    fn snippets_group(&mut self, ui: &mut egui::Ui) {
+        ui.label("script snippets");
+        let library = self
+            .snippet_library
+            .get_or_insert_with(|| snippets::library(&self.snippets.dirs));
+        let library = match library {
+            Ok(library) => library,
+            Err(e) => {
+                ui.colored_label(Color32::RED, format!("{:#}", e));
+                if ui.button("Reload").clicked() {
+                    self.snippet_library = None;
+                }
+                return;
+            }
+        };
+        self.snippet_pick = self.snippet_pick.min(library.len().saturating_sub(1));
+        if let Some(picked) = library.get(self.snippet_pick) {
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_id_source("snippet_pick")
+                    .selected_text(&picked.name)
+                    .show_ui(ui, |ui| {
+                        for (i, snippet) in library.iter().enumerate() {
+                            ui.selectable_value(&mut self.snippet_pick, i, &snippet.name);
+                        }
+                    });
+                if ui.button("Add to project").clicked() {
+                    self.snippets.uses.push(SnippetUse {
+                        name: picked.name.clone(),
+                        params: Default::default(),
+                    });
+                }
+                let script = match picked.script.as_str() {
+                    "preinst" => &mut self.preinst,
+                    "prerm" => &mut self.prerm,
+                    _ => &mut self.postinst,
+                };
+                let editable = script.file_or_text == ScriptSource::FromTextfield;
+                if ui
+                    .add_enabled(editable, egui::Button::new(format!("Insert into {}", picked.script)))
+                    .clicked()
+                {
+                    script.enabled = true;
+                    if !script.from_textbox.ends_with('\n') {
+                        script.from_textbox.push('\n');
+                    }
+                    script.from_textbox.push_str(&picked.fill(&Default::default()));
+                }
+            });
+            ui.label(
+                RichText::new(format!("{} ({} on {})", picked.description, picked.script, picked.when.join(", ")))
+                    .small(),
+            );
+        }
+
+        let mut removed = None;
+        for (i, used) in self.snippets.uses.iter_mut().enumerate() {
+            ui.group(|ui| {
+                ui.horizontal(|ui| {
+                    ui.label(RichText::new(&used.name).monospace());
+                    if ui.button("Remove").clicked() {
+                        removed = Some(i);
+                    }
+                });
+                let snippet = library.iter().find(|s| s.name == used.name);
+                let Some(snippet) = snippet else {
+                    ui.colored_label(Color32::RED, "Not in the snippet library");
+                    return;
+                };
+                egui::Grid::new(("snippet_params", i)).num_columns(2).show(ui, |ui| {
+                    for (name, default) in &snippet.params {
+                        ui.label(name);
+                        let value = used.params.entry(name.clone()).or_default();
+                        ui.add(egui::TextEdit::singleline(value).hint_text(default));
+                        ui.end_row();
+                    }
+                });
+                if let Err(e) = snippet.render(&used.params) {
+                    ui.colored_label(Color32::RED, format!("{:#}", e));
+                }
+            });
+        }
+        if let Some(i) = removed {
+            self.snippets.uses.remove(i);
+        }
+
+        ui.label("snippet folders");
+        let mut removed = None;
+        for (i, dir) in self.snippets.dirs.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(dir.to_string_lossy()).monospace());
+                if ui.button("Remove").clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        if let Some(i) = removed {
+            self.snippets.dirs.remove(i);
+            self.snippet_library = None;
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Add folder...").clicked() {
+                if let Some(path) = self.session.pick_folder() {
+                    self.snippets.dirs.push(path);
+                    self.snippet_library = None;
+                }
+            }
+            if ui.button("Reload").clicked() {
+                self.snippet_library = None;
+            }
+        });
+    }
+
+    /// Called after drawing the group of `section`: scrolls to it if a
+    /// problem link asked for it, and takes files dropped onto it.
+    fn group_done(&mut self, ui: &egui::Ui, section: Section, response: &egui::Response) {
+        if self.jump_to == Some(section) {
+            response.scroll_to_me(Some(egui::Align::TOP));
+            self.jump_to = None;
+        }
+        let pointer_inside = ui
+            .input(|i| i.pointer.hover_pos())
+            .is_some_and(|pos| response.rect.contains(pos));
+        if !pointer_inside {
+            return;
+        }
+        if ui.input(|i| !i.raw.hovered_files.is_empty()) {
+            ui.painter().rect_stroke(
+                response.rect,
+                4.,
+                egui::Stroke::new(2., ui.visuals().selection.stroke.color),
+            );
+        }
+        if let Some(path) = self.dropped.take() {
+            if let Err(e) = self.drop_on(section, path) {
+                self.success_or_not = Err(e);
+            }
+        }
+    }
+
+    /// Use a file or folder dropped onto the group of `section`.
+    fn drop_on(&mut self, section: Section, path: PathBuf) -> Result<()> {
+        let is_dir = path.is_dir();
+        let folder = matches!(section, Section::DataRoot | Section::Output);
+        if is_dir != folder {
+            bail!(
+                "{} is a {}, this field takes a {}",
+                path.display(),
+                if is_dir { "folder" } else { "file" },
+                if folder { "folder" } else { "file" }
+            );
+        }
+        let file = match section {
+            Section::Control => &mut self.control_file,
+            Section::DebianBinary => {
+                self.debian_binary.enabled = false;
+                &mut self.debian_binary
+            }
+            Section::Postinst => &mut self.postinst,
+            Section::Preinst => &mut self.preinst,
+            Section::Prerm => &mut self.prerm,
+            Section::Manifest => &mut self.manifest,
+            Section::Service => {
+                self.service.enabled = true;
+                self.service.file = Some(path);
+                return Ok(());
+            }
+            Section::DataRoot => {
+                self.data_path = Some(path.display().to_string());
+                self.data_tree = None;
+                return Ok(());
+            }
+            Section::Output => {
+                self.output_path = Some(path.display().to_string());
+                return Ok(());
+            }
+        };
+        if section != Section::DebianBinary {
+            file.enabled = true;
+        }
+        file.file_or_text = ScriptSource::FromPath;
+        file.picked_path = Some(path);
+        Ok(())
+    }
+
+    /// Import the control file and maintainer scripts of an existing package
+    /// into the text fields. Its data is not imported, the data folder and
+    /// install manifest stay as they are.
+    fn import_control_and_scripts(&mut self, path: &Path) -> Result<()> {
+        let mut members = feed::control_members(path)?;
+        let text = |content: Vec<u8>, name: &str| {
+            String::from_utf8(content).context(format!("{} of {} is not UTF-8", name, path.display()))
+        };
+        let Some(control) = members.remove("control") else {
+            bail!("{} has no control file", path.display());
+        };
+        self.control_file.file_or_text = ScriptSource::FromTextfield;
+        self.control_file.from_textbox = text(control, "control")?;
+        for (name, script) in [
+            ("postinst", &mut self.postinst),
+            ("preinst", &mut self.preinst),
+            ("prerm", &mut self.prerm),
+        ] {
+            script.enabled = members.contains_key(name);
+            if let Some(content) = members.remove(name) {
+                script.file_or_text = ScriptSource::FromTextfield;
+                script.from_textbox = text(content, name)?;
+            }
+        }
+        self.success_or_not = Ok(format!(
+            "Imported control and scripts of {}, the data folder and manifest are unchanged",
+            path.display()
+        ));
+        Ok(())
+    }
+
+    /// Open dropped project files and import the control and scripts of
+    /// dropped packages right away, and keep other paths for the group they
+    /// were dropped onto.
+    fn take_dropped(&mut self, ctx: &egui::Context) {
+        let dropped: Vec<PathBuf> =
+            ctx.input(|i| i.raw.dropped_files.iter().filter_map(|f| f.path.clone()).collect());
+        for path in dropped {
+            match path.extension().and_then(|e| e.to_str()) {
+                Some("toml") => self.open_project(&path),
+                Some("ipk") => {
+                    if let Err(e) = self.import_control_and_scripts(&path) {
+                        self.success_or_not = Err(e);
+                    }
+                }
+                // one path per field, the last one wins
+                _ => self.dropped = Some(path),
+            }
+        }
+    }
+
+    /// Start building a copy of the current settings in the background.
+    fn start_build(&mut self) {
+        if self.build.is_some() {
+            return;
+        }
+        let data = match project::snapshot(self) {
+            Ok(data) => data,
+            Err(e) => {
+                self.success_or_not = Err(e);
+                return;
+            }
+        };
+        console::separator(match &self.project_path {
+            Some(project) => format!("Build of {}", project.display()),
+            None => "Build".to_owned(),
+        });
+        let progress = Progress::default();
+        let handle = {
+            let progress = progress.clone();
+            thread::spawn(move || {
+                let built = make_packages(&data, &progress)?;
+                Ok(built
+                    .iter()
+                    .map(|p| format!("{}\n{}", p.path.display(), p.verification))
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            })
+        };
+        self.build = Some(BuildJob { progress, handle });
+    }
+
+    /// Pick up the result of a finished build.
+    fn poll_build(&mut self) {
+        if let Some(job) = self.build.take_if(|job| job.handle.is_finished()) {
+            self.success_or_not = job
+                .handle
+                .join()
+                .unwrap_or_else(|_| Err(anyhow!("Build thread panicked")));
+            match &self.success_or_not {
+                Ok(packages) => info!("Build finished: {}", packages),
+                // the debug format lists every context the error passed through
+                Err(e) => error!("Build failed: {:?}", e),
+            }
+        }
+    }
+
+    fn save_project_as(&mut self) {
+        if let Some(path) = self.session.save_project_file() {
+            self.save_project(path);
         }
     }
 }
 impl eframe::App for IpkBuilder {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.session.project_path = self.project_path.clone();
+        eframe::set_value(storage, eframe::APP_KEY, self);
+        eframe::set_value(storage, SESSION_KEY, &self.session);
+    }
+
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        self.poll_build();
+        self.take_dropped(ctx);
+        if self.watcher.as_ref().is_some_and(|w| !w.watches(self)) {
+            info!("Inputs changed, watching the new ones");
+            self.watcher = None;
+            match Watcher::new(self, DEFAULT_DEBOUNCE) {
+                Ok(watcher) => self.watcher = Some(watcher),
+                Err(e) => self.success_or_not = Err(e),
+            }
+        }
+        if let Some(watcher) = &mut self.watcher {
+            // changes seen during a build are picked up once it is done
+            if self.build.is_none() && watcher.poll() {
+                match validate::check(self) {
+                    Ok(()) => self.start_build(),
+                    Err(e) => self.success_or_not = Err(e),
+                }
+            }
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        }
+        if self.build.is_some() {
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        }
+
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             // The top panel is often a good place for a menu bar:
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
+                    // replacing the state drops a running build and the watcher
+                    let idle = self.build.is_none();
+                    if ui.add_enabled(idle, egui::Button::new("New")).clicked() {
+                        let session = std::mem::take(&mut self.session);
+                        *self = Default::default();
+                        self.session = session;
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(idle, egui::Button::new("Open...")).clicked() {
+                        if let Some(path) = self.session.pick_project() {
+                            self.open_project(&path);
+                        }
+                        ui.close_menu();
+                    }
+                    ui.add_enabled_ui(idle && !self.session.recent_projects.is_empty(), |ui| {
+                        ui.menu_button("Open Recent", |ui| {
+                            let mut picked = None;
+                            for recent in &self.session.recent_projects {
+                                if ui.button(recent.to_string_lossy()).clicked() {
+                                    picked = Some(recent.clone());
+                                }
+                            }
+                            if let Some(path) = picked {
+                                self.open_project(&path);
+                                ui.close_menu();
+                            }
+                        });
+                    });
+                    if ui.button("Save").clicked() {
+                        match self.project_path.clone() {
+                            Some(path) => self.save_project(path),
+                            None => self.save_project_as(),
+                        }
+                        ui.close_menu();
+                    }
+                    if ui.button("Save As...").clicked() {
+                        self.save_project_as();
+                        ui.close_menu();
+                    }
+                    ui.separator();
                     if ui.button("Quit").clicked() {
                         frame.close();
                     }
                 });
+                ui.menu_button("View", |ui| {
+                    ui.checkbox(&mut self.console.open, "Log console");
+                });
             });
         });
 
+        if self.console.open {
+            egui::TopBottomPanel::bottom("log_console")
+                .resizable(true)
+                .default_height(200.)
+                .show(ctx, |ui| self.console.show(ui));
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.group(|ui| {
-                ui.vertical_centered_justified(|ui| {
-                    ui.label("control file");
-                    ui.horizontal(|ui| {
-                        if ui
-                            .add(egui::RadioButton::new(
-                                self.control_file.file_or_text == ScriptSource::FromPath,
-                                "from file",
-                            ))
-                            .clicked()
-                        {
-                            self.control_file.file_or_text = ScriptSource::FromPath;
-                        }
-                        if ui
-                            .add(egui::RadioButton::new(
-                                self.control_file.file_or_text == ScriptSource::FromTextfield,
-                                "from input field",
-                            ))
-                            .clicked()
-                        {
-                            self.control_file.file_or_text = ScriptSource::FromTextfield;
-                        }
-                        if self.control_file.file_or_text == ScriptSource::FromPath {
-                            if ui.button("Open file...").clicked() {
-                                if let Some(path) = rfd::FileDialog::new().pick_file() {
-                                    self.control_file.picked_path =
-                                        Some(path);
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                let group = ui.group(|ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        ui.label("control file");
+                        ui.horizontal(|ui| {
+                            if ui
+                                .add(egui::RadioButton::new(
+                                    self.control_file.file_or_text == ScriptSource::FromPath,
+                                    "from file",
+                                ))
+                                .clicked()
+                            {
+                                self.control_file.file_or_text = ScriptSource::FromPath;
+                            }
+                            if ui
+                                .add(egui::RadioButton::new(
+                                    self.control_file.file_or_text == ScriptSource::FromTextfield,
+                                    "from input field",
+                                ))
+                                .clicked()
+                            {
+                                self.control_file.file_or_text = ScriptSource::FromTextfield;
+                            }
+                            if self.control_file.file_or_text == ScriptSource::FromPath {
+                                if ui.button("Open file...").clicked() {
+                                    if let Some(path) = self.session.pick_file() {
+                                        self.control_file.picked_path =
+                                            Some(path);
+                                    }
                                 }
+                            } else {
+                                ui.add_enabled(false, egui::Button::new("Open file..."));
+                            };
+                        });
+                        if let Some(picked_path) = &self.control_file.picked_path {
+                            ui.horizontal(|ui| {
+                                ui.label("Picked file:");
+                                ui.add(
+                                    egui::Label::new(RichText::new(picked_path.to_string_lossy()).monospace()).wrap(true),
+                                );
+                            });
+                        }
+                        if self.control_file.file_or_text == ScriptSource::FromTextfield {
+                            ui.horizontal(|ui| {
+                                ui.selectable_value(&mut self.control_form, false, "raw text");
+                                ui.selectable_value(&mut self.control_form, true, "form");
+                            });
+                            if self.control_form {
+                                form::show(ui, &mut self.control_file.from_textbox, &mut self.architectures);
+                            } else {
+                                let _ = ui.add(
+                                    egui::TextEdit::multiline(&mut self.control_file.from_textbox)
+                                        .code_editor(),
+                                );
                             }
-                        } else {
-                            ui.add_enabled(false, egui::Button::new("Open file..."));
-                        };
+                        }
                     });
-                    if let Some(picked_path) = &self.control_file.picked_path {
+                });
+                self.group_done(ui, Section::Control, &group.response);
+                let group = ui.group(|ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        ui.label("debian binary");
+                        ui.checkbox(&mut self.debian_binary.enabled, "default");
                         ui.horizontal(|ui| {
-                            ui.label("Picked file:");
-                            ui.add(
-                                egui::Label::new(RichText::new(picked_path.to_string_lossy()).monospace()).wrap(true),
-                            );
+                            if !self.debian_binary.enabled {
+                                ui.horizontal(|ui| {
+                                    if ui
+                                        .add(egui::RadioButton::new(
+                                            self.debian_binary.file_or_text == ScriptSource::FromPath,
+                                            "from file",
+                                        ))
+                                        .clicked()
+                                    {
+                                        self.debian_binary.file_or_text = ScriptSource::FromPath;
+                                    }
+                                    if ui
+                                        .add(egui::RadioButton::new(
+                                            self.debian_binary.file_or_text
+                                                == ScriptSource::FromTextfield,
+                                            "from input field",
+                                        ))
+                                        .clicked()
+                                    {
+                                        self.debian_binary.file_or_text = ScriptSource::FromTextfield;
+                                    }
+                                    if self.debian_binary.file_or_text == ScriptSource::FromPath {
+                                        if ui.button("Open file...").clicked() {
+                                            if let Some(path) = self.session.pick_file() {
+                                                self.debian_binary.picked_path =
+                                                    Some(path);
+                                            }
+                                        }
+                                    } else {
+                                        ui.add_enabled(false, egui::Button::new("Open file..."));
+                                    };
+                                });
+                            }
                         });
-                    }
-                    if self.control_file.file_or_text == ScriptSource::FromTextfield {
-                        let _ = ui.add(
-                            egui::TextEdit::multiline(&mut self.control_file.from_textbox)
-                                .code_editor(),
-                        );
-                    }
-                });
-            });
-            ui.group(|ui| {
-                ui.vertical_centered_justified(|ui| {
-                    ui.label("debian binary");
-                    ui.checkbox(&mut self.debian_binary.enabled, "default");
-                    ui.horizontal(|ui| {
                         if !self.debian_binary.enabled {
-                            ui.horizontal(|ui| {
-                                if ui
-                                    .add(egui::RadioButton::new(
-                                        self.debian_binary.file_or_text == ScriptSource::FromPath,
-                                        "from file",
-                                    ))
-                                    .clicked()
-                                {
-                                    self.debian_binary.file_or_text = ScriptSource::FromPath;
-                                }
-                                if ui
-                                    .add(egui::RadioButton::new(
-                                        self.debian_binary.file_or_text
-                                            == ScriptSource::FromTextfield,
-                                        "from input field",
-                                    ))
-                                    .clicked()
-                                {
-                                    self.debian_binary.file_or_text = ScriptSource::FromTextfield;
-                                }
-                                if self.debian_binary.file_or_text == ScriptSource::FromPath {
-                                    if ui.button("Open file...").clicked() {
-                                        if let Some(path) = rfd::FileDialog::new().pick_file() {
-                                            self.debian_binary.picked_path =
-                                                Some(path);
-                                        }
+                            if let Some(picked_path) = &self.debian_binary.picked_path {
+                                ui.horizontal(|ui| {
+                                    ui.label("Picked file:");
+                                    ui.add(
+                                        egui::Label::new(RichText::new(picked_path.to_string_lossy()).monospace())
+                                            .wrap(true),
+                                    );
+                                });
+                            }
+                            if self.debian_binary.file_or_text == ScriptSource::FromTextfield {
+                                let _ = ui.add(
+                                    egui::TextEdit::multiline(&mut self.debian_binary.from_textbox)
+                                        .code_editor(),
+                                );
+                            }
+                        }
+                    });
+                });
+                self.group_done(ui, Section::DebianBinary, &group.response);
+                let group = ui.group(|ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        ui.label("postinst script");
+                        ui.checkbox(&mut self.postinst.enabled, "use");
+                        ui.horizontal(|ui| {
+                            if self.postinst.enabled {
+                                ui.horizontal(|ui| {
+                                    if ui
+                                        .add(egui::RadioButton::new(
+                                            self.postinst.file_or_text == ScriptSource::FromPath,
+                                            "from file",
+                                        ))
+                                        .clicked()
+                                    {
+                                        self.postinst.file_or_text = ScriptSource::FromPath;
                                     }
-                                } else {
-                                    ui.add_enabled(false, egui::Button::new("Open file..."));
-                                };
-                            });
+                                    if ui
+                                        .add(egui::RadioButton::new(
+                                            self.postinst.file_or_text == ScriptSource::FromTextfield,
+                                            "from input field",
+                                        ))
+                                        .clicked()
+                                    {
+                                        self.postinst.file_or_text = ScriptSource::FromTextfield;
+                                    }
+                                    if self.postinst.file_or_text == ScriptSource::FromPath {
+                                        if ui.button("Open file...").clicked() {
+                                            if let Some(path) = self.session.pick_file() {
+                                                self.postinst.picked_path =
+                                                    Some(path);
+                                            }
+                                        }
+                                    } else {
+                                        ui.add_enabled(false, egui::Button::new("Open file..."));
+                                    };
+                                });
+                            }
+                        });
+
+                        if self.postinst.enabled {
+                            if let Some(picked_path) = &self.postinst.picked_path {
+                                ui.horizontal(|ui| {
+                                    ui.label("Picked file:");
+                                    ui.add(
+                                        egui::Label::new(RichText::new(picked_path.to_string_lossy()).monospace())
+                                            .wrap(true),
+                                    );
+                                });
+                            }
+                            if self.postinst.file_or_text == ScriptSource::FromTextfield {
+                                shell::editor(ui, &mut self.postinst.from_textbox, "postinst");
+                            }
                         }
                     });
-                    if !self.debian_binary.enabled {
-                        if let Some(picked_path) = &self.debian_binary.picked_path {
-                            ui.horizontal(|ui| {
-                                ui.label("Picked file:");
-                                ui.add(
-                                    egui::Label::new(RichText::new(picked_path.to_string_lossy()).monospace())
-                                        .wrap(true),
-                                );
-                            });
+                });
+                self.group_done(ui, Section::Postinst, &group.response);
+
+                let group = ui.group(|ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        ui.label("preinst script");
+                        ui.checkbox(&mut self.preinst.enabled, "use");
+                        ui.horizontal(|ui| {
+                            if self.preinst.enabled {
+                                ui.horizontal(|ui| {
+                                    if ui
+                                        .add(egui::RadioButton::new(
+                                            self.preinst.file_or_text == ScriptSource::FromPath,
+                                            "from file",
+                                        ))
+                                        .clicked()
+                                    {
+                                        self.preinst.file_or_text = ScriptSource::FromPath;
+                                    }
+                                    if ui
+                                        .add(egui::RadioButton::new(
+                                            self.preinst.file_or_text == ScriptSource::FromTextfield,
+                                            "from input field",
+                                        ))
+                                        .clicked()
+                                    {
+                                        self.preinst.file_or_text = ScriptSource::FromTextfield;
+                                    }
+                                    if self.preinst.file_or_text == ScriptSource::FromPath {
+                                        if ui.button("Open file...").clicked() {
+                                            if let Some(path) = self.session.pick_file() {
+                                                self.preinst.picked_path =
+                                                    Some(path);
+                                            }
+                                        }
+                                    } else {
+                                        ui.add_enabled(false, egui::Button::new("Open file..."));
+                                    };
+                                });
+                            }
+                        });
+
+                        if self.preinst.enabled {
+                            if let Some(picked_path) = &self.preinst.picked_path {
+                                ui.horizontal(|ui| {
+                                    ui.label("Picked file:");
+                                    ui.add(
+                                        egui::Label::new(RichText::new(picked_path.to_string_lossy()).monospace())
+                                            .wrap(true),
+                                    );
+                                });
+                            }
+                            if self.preinst.file_or_text == ScriptSource::FromTextfield {
+                                shell::editor(ui, &mut self.preinst.from_textbox, "preinst");
+                            }
                         }
-                        if self.debian_binary.file_or_text == ScriptSource::FromTextfield {
-                            let _ = ui.add(
-                                egui::TextEdit::multiline(&mut self.debian_binary.from_textbox)
-                                    .code_editor(),
-                            );
+                    });
+                });
+                self.group_done(ui, Section::Preinst, &group.response);
+
+                let group = ui.group(|ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        ui.label("prerm script");
+                        ui.checkbox(&mut self.prerm.enabled, "use");
+                        ui.horizontal(|ui| {
+                            if self.prerm.enabled {
+                                ui.horizontal(|ui| {
+                                    if ui
+                                        .add(egui::RadioButton::new(
+                                            self.prerm.file_or_text == ScriptSource::FromPath,
+                                            "from file",
+                                        ))
+                                        .clicked()
+                                    {
+                                        self.prerm.file_or_text = ScriptSource::FromPath;
+                                    }
+                                    if ui
+                                        .add(egui::RadioButton::new(
+                                            self.prerm.file_or_text == ScriptSource::FromTextfield,
+                                            "from input field",
+                                        ))
+                                        .clicked()
+                                    {
+                                        self.prerm.file_or_text = ScriptSource::FromTextfield;
+                                    }
+                                    if self.prerm.file_or_text == ScriptSource::FromPath {
+                                        if ui.button("Open file...").clicked() {
+                                            if let Some(path) = self.session.pick_file() {
+                                                self.prerm.picked_path =
+                                                    Some(path);
+                                            }
+                                        }
+                                    } else {
+                                        ui.add_enabled(false, egui::Button::new("Open file..."));
+                                    };
+                                });
+                            }
+                        });
+
+                        if self.prerm.enabled {
+                            if let Some(picked_path) = &self.prerm.picked_path {
+                                ui.horizontal(|ui| {
+                                    ui.label("Picked file:");
+                                    ui.add(
+                                        egui::Label::new(RichText::new(picked_path.to_string_lossy()).monospace())
+                                            .wrap(true),
+                                    );
+                                });
+                            }
+                            if self.prerm.file_or_text == ScriptSource::FromTextfield {
+                                shell::editor(ui, &mut self.prerm.from_textbox, "prerm");
+                            }
                         }
-                    }
+                    });
                 });
-            });
-            ui.group(|ui| {
-                ui.vertical_centered_justified(|ui| {
-                    ui.label("postinst script");
-                    ui.checkbox(&mut self.postinst.enabled, "use");
-                    ui.horizontal(|ui| {
-                        if self.postinst.enabled {
+                self.group_done(ui, Section::Prerm, &group.response);
+
+                ui.group(|ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        self.snippets_group(ui);
+                    });
+                });
+
+                let group = ui.group(|ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        ui.label("service");
+                        ui.checkbox(&mut self.service.enabled, "use");
+                        if self.service.enabled {
                             ui.horizontal(|ui| {
-                                if ui
-                                    .add(egui::RadioButton::new(
-                                        self.postinst.file_or_text == ScriptSource::FromPath,
-                                        "from file",
-                                    ))
-                                    .clicked()
-                                {
-                                    self.postinst.file_or_text = ScriptSource::FromPath;
-                                }
-                                if ui
-                                    .add(egui::RadioButton::new(
-                                        self.postinst.file_or_text == ScriptSource::FromTextfield,
-                                        "from input field",
-                                    ))
-                                    .clicked()
-                                {
-                                    self.postinst.file_or_text = ScriptSource::FromTextfield;
+                                for init in InitSystem::ALL {
+                                    ui.radio_value(&mut self.service.init, init, init.label());
                                 }
-                                if self.postinst.file_or_text == ScriptSource::FromPath {
-                                    if ui.button("Open file...").clicked() {
-                                        if let Some(path) = rfd::FileDialog::new().pick_file() {
-                                            self.postinst.picked_path =
-                                                Some(path);
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("service name");
+                                ui.text_edit_singleline(&mut self.service.name);
+                            });
+                            ui.horizontal(|ui| {
+                                if ui.button("Open file...").clicked() {
+                                    if let Some(path) = self.session.pick_file() {
+                                        if self.service.name.is_empty() {
+                                            self.service.name = path
+                                                .file_stem()
+                                                .unwrap_or_default()
+                                                .to_string_lossy()
+                                                .into_owned();
                                         }
+                                        self.service.file = Some(path);
                                     }
-                                } else {
-                                    ui.add_enabled(false, egui::Button::new("Open file..."));
-                                };
+                                }
+                                if let Some(file) = &self.service.file {
+                                    ui.add(
+                                        egui::Label::new(RichText::new(file.to_string_lossy()).monospace()).wrap(true),
+                                    );
+                                }
                             });
+                            ui.checkbox(&mut self.service.enable_on_install, "enable and start on install");
+                            ui.checkbox(&mut self.service.restart_on_upgrade, "restart on upgrade");
+                            if let Ok(Some(file)) = self.service.install_file() {
+                                ui.label(format!("installed as /{}", file.dest.display()));
+                            }
                         }
                     });
+                });
+                self.group_done(ui, Section::Service, &group.response);
 
-                    if self.postinst.enabled {
-                        if let Some(picked_path) = &self.postinst.picked_path {
-                            ui.horizontal(|ui| {
-                                ui.label("Picked file:");
-                                ui.add(
-                                    egui::Label::new(RichText::new(picked_path.to_string_lossy()).monospace())
-                                        .wrap(true),
+                let group = ui.group(|ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        ui.label("install manifest");
+                        ui.checkbox(&mut self.manifest.enabled, "use");
+                        ui.horizontal(|ui| {
+                            if self.manifest.enabled {
+                                ui.horizontal(|ui| {
+                                    if ui
+                                        .add(egui::RadioButton::new(
+                                            self.manifest.file_or_text == ScriptSource::FromPath,
+                                            "from file",
+                                        ))
+                                        .clicked()
+                                    {
+                                        self.manifest.file_or_text = ScriptSource::FromPath;
+                                    }
+                                    if ui
+                                        .add(egui::RadioButton::new(
+                                            self.manifest.file_or_text == ScriptSource::FromTextfield,
+                                            "from input field",
+                                        ))
+                                        .clicked()
+                                    {
+                                        self.manifest.file_or_text = ScriptSource::FromTextfield;
+                                    }
+                                    if self.manifest.file_or_text == ScriptSource::FromPath {
+                                        if ui.button("Open file...").clicked() {
+                                            if let Some(path) = self.session.pick_file() {
+                                                self.manifest.picked_path =
+                                                    Some(path);
+                                            }
+                                        }
+                                    } else {
+                                        ui.add_enabled(false, egui::Button::new("Open file..."));
+                                    };
+                                });
+                            }
+                        });
+
+                        if self.manifest.enabled {
+                            if let Some(picked_path) = &self.manifest.picked_path {
+                                ui.horizontal(|ui| {
+                                    ui.label("Picked file:");
+                                    ui.add(
+                                        egui::Label::new(RichText::new(picked_path.to_string_lossy()).monospace())
+                                            .wrap(true),
+                                    );
+                                });
+                            }
+                            if self.manifest.file_or_text == ScriptSource::FromTextfield {
+                                let _ = ui.add(
+                                    egui::TextEdit::multiline(&mut self.manifest.from_textbox)
+                                        .code_editor(),
                                 );
+                            }
+                        }
+                    });
+                });
+                self.group_done(ui, Section::Manifest, &group.response);
+
+                let group = ui.group(|ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        ui.label("Data folder root");
+                        ui.horizontal(|ui| {
+                            ui.horizontal(|ui| {
+                                if ui.button("Set path..").clicked() {
+                                    if let Some(path) = self.session.pick_folder() {
+                                        self.data_path = Some(path.display().to_string());
+                                        self.data_tree = None;
+                                    }
+                                }
                             });
+                        });
+                    });
+                    if let Some(picked_path) = &self.data_path {
+                        ui.horizontal(|ui| {
+                            ui.label("Picked path:");
+                            ui.add(egui::Label::new(RichText::new(picked_path).monospace()).wrap(true));
+                        });
+                    }
+                    ui.label(format!("exclude patterns (gitignore syntax, also read from {})", exclude::IGNORE_FILE));
+                    let _ = ui.add(
+                        egui::TextEdit::multiline(&mut self.exclude)
+                            .code_editor()
+                            .desired_rows(2),
+                    );
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(self.data_path.is_some(), egui::Button::new("Dry run")).clicked() {
+                            self.dry_run = Some(data_tree(self).map(|tree| exclude::listing(&tree)));
                         }
-                        if self.postinst.file_or_text == ScriptSource::FromTextfield {
-                            let _ = ui.add(
-                                egui::TextEdit::multiline(&mut self.postinst.from_textbox)
-                                    .code_editor(),
-                            );
+                        if self.dry_run.is_some() && ui.button("Close listing").clicked() {
+                            self.dry_run = None;
                         }
+                        if let Some(data_path) = &self.data_path {
+                            let label = if self.data_tree.is_some() { "Refresh tree" } else { "Browse tree" };
+                            if ui.button(label).clicked() {
+                                self.data_tree = Some(tree::scan(Path::new(data_path), &self.exclude));
+                            }
+                        }
+                        if self.data_tree.is_some() && ui.button("Close tree").clicked() {
+                            self.data_tree = None;
+                        }
+                    });
+                    match &self.data_tree {
+                        Some(Ok(nodes)) => tree::show(ui, nodes, &mut self.overrides),
+                        Some(Err(e)) => {
+                            ui.colored_label(Color32::RED, format!("{:#}", e));
+                        }
+                        None => {}
+                    }
+                    match &self.dry_run {
+                        Some(Ok(listing)) => {
+                            ui.add(egui::Label::new(RichText::new(listing).monospace()).wrap(true));
+                        }
+                        Some(Err(e)) => {
+                            ui.colored_label(Color32::RED, format!("{:#}", e));
+                        }
+                        None => {}
                     }
                 });
-            });
+                self.group_done(ui, Section::DataRoot, &group.response);
 
-            ui.group(|ui| {
-                ui.vertical_centered_justified(|ui| {
-                    ui.label("preinst script");
-                    ui.checkbox(&mut self.preinst.enabled, "use");
-                    ui.horizontal(|ui| {
-                        if self.preinst.enabled {
-                            ui.horizontal(|ui| {
-                                if ui
-                                    .add(egui::RadioButton::new(
-                                        self.preinst.file_or_text == ScriptSource::FromPath,
-                                        "from file",
-                                    ))
-                                    .clicked()
-                                {
-                                    self.preinst.file_or_text = ScriptSource::FromPath;
-                                }
-                                if ui
-                                    .add(egui::RadioButton::new(
-                                        self.preinst.file_or_text == ScriptSource::FromTextfield,
-                                        "from input field",
-                                    ))
-                                    .clicked()
-                                {
-                                    self.preinst.file_or_text = ScriptSource::FromTextfield;
-                                }
-                                if self.preinst.file_or_text == ScriptSource::FromPath {
+                ui.group(|ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        ui.label("shared library dependencies");
+                        ui.checkbox(&mut self.shlibdeps.enabled, "generate Depends");
+                        if self.shlibdeps.enabled {
+                            for (label, path) in [
+                                ("feed index", &mut self.shlibdeps.feed_index),
+                                ("Contents index", &mut self.shlibdeps.contents_index),
+                                ("SONAME map", &mut self.shlibdeps.soname_map),
+                            ] {
+                                ui.horizontal(|ui| {
+                                    ui.label(label);
                                     if ui.button("Open file...").clicked() {
-                                        if let Some(path) = rfd::FileDialog::new().pick_file() {
-                                            self.preinst.picked_path =
-                                                Some(path);
+                                        if let Some(picked) = self.session.pick_file() {
+                                            *path = Some(picked);
                                         }
                                     }
-                                } else {
-                                    ui.add_enabled(false, egui::Button::new("Open file..."));
-                                };
-                            });
+                                    if path.is_some() && ui.button("Clear").clicked() {
+                                        *path = None;
+                                    }
+                                });
+                                if let Some(picked_path) = path {
+                                    ui.horizontal(|ui| {
+                                        ui.label("Picked file:");
+                                        ui.add(
+                                            egui::Label::new(RichText::new(picked_path.to_string_lossy()).monospace())
+                                                .wrap(true),
+                                        );
+                                    });
+                                }
+                            }
                         }
                     });
+                });
 
-                    if self.preinst.enabled {
-                        if let Some(picked_path) = &self.preinst.picked_path {
-                            ui.horizontal(|ui| {
-                                ui.label("Picked file:");
-                                ui.add(
-                                    egui::Label::new(RichText::new(picked_path.to_string_lossy()).monospace())
-                                        .wrap(true),
+                ui.group(|ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        ui.label("split packages");
+                        let mut remove = None;
+                        for (i, package) in self.split_packages.iter_mut().enumerate() {
+                            ui.group(|ui| {
+                                ui.horizontal(|ui| {
+                                    ui.label("Package:");
+                                    ui.text_edit_singleline(&mut package.name);
+                                    if ui.button("Remove").clicked() {
+                                        remove = Some(i);
+                                    }
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.label("FILES:");
+                                    ui.text_edit_singleline(&mut package.files);
+                                });
+                                let _ = ui.add(
+                                    egui::TextEdit::multiline(&mut package.control)
+                                        .code_editor()
+                                        .desired_rows(2),
                                 );
                             });
                         }
-                        if self.preinst.file_or_text == ScriptSource::FromTextfield {
-                            let _ = ui.add(
-                                egui::TextEdit::multiline(&mut self.preinst.from_textbox)
-                                    .code_editor(),
-                            );
+                        if let Some(i) = remove {
+                            self.split_packages.remove(i);
                         }
-                    }
+                        if ui.button("Add package").clicked() {
+                            self.split_packages.push(Default::default());
+                        }
+                    });
                 });
-            });
 
-            ui.group(|ui| {
-                ui.vertical_centered_justified(|ui| {
-                    ui.label("prerm script");
-                    ui.checkbox(&mut self.prerm.enabled, "use");
-                    ui.horizontal(|ui| {
-                        if self.prerm.enabled {
+                ui.group(|ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        ui.label("variables");
+                        ui.checkbox(&mut self.variables.enabled, "substitute ${VARIABLES}");
+                        if self.variables.enabled {
+                            ui.label("NAME=value, one per line; the environment is ${ENV_NAME}");
+                            let _ = ui.add(
+                                egui::TextEdit::multiline(&mut self.variables.defined)
+                                    .code_editor()
+                                    .desired_rows(2),
+                            );
                             ui.horizontal(|ui| {
-                                if ui
-                                    .add(egui::RadioButton::new(
-                                        self.prerm.file_or_text == ScriptSource::FromPath,
-                                        "from file",
-                                    ))
-                                    .clicked()
-                                {
-                                    self.prerm.file_or_text = ScriptSource::FromPath;
+                                if ui.button("Preview").clicked() {
+                                    self.variables_preview = Some(preview_variables(self));
                                 }
-                                if ui
-                                    .add(egui::RadioButton::new(
-                                        self.prerm.file_or_text == ScriptSource::FromTextfield,
-                                        "from input field",
-                                    ))
-                                    .clicked()
-                                {
-                                    self.prerm.file_or_text = ScriptSource::FromTextfield;
+                                if self.variables_preview.is_some() && ui.button("Close preview").clicked() {
+                                    self.variables_preview = None;
                                 }
-                                if self.prerm.file_or_text == ScriptSource::FromPath {
-                                    if ui.button("Open file...").clicked() {
-                                        if let Some(path) = rfd::FileDialog::new().pick_file() {
-                                            self.prerm.picked_path =
-                                                Some(path);
-                                        }
-                                    }
-                                } else {
-                                    ui.add_enabled(false, egui::Button::new("Open file..."));
-                                };
                             });
+                            match &self.variables_preview {
+                                Some(Ok(preview)) => {
+                                    ui.add(egui::Label::new(RichText::new(preview).monospace()).wrap(true));
+                                }
+                                Some(Err(e)) => {
+                                    ui.colored_label(Color32::RED, format!("{:#}", e));
+                                }
+                                None => {}
+                            }
                         }
                     });
+                });
 
-                    if self.prerm.enabled {
-                        if let Some(picked_path) = &self.prerm.picked_path {
+                ui.group(|ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        ui.label("version from git");
+                        ui.checkbox(&mut self.git_version.enabled, "derive Version from git describe");
+                        if self.git_version.enabled {
                             ui.horizontal(|ui| {
-                                ui.label("Picked file:");
-                                ui.add(
-                                    egui::Label::new(RichText::new(picked_path.to_string_lossy()).monospace())
-                                        .wrap(true),
-                                );
+                                ui.checkbox(&mut self.git_version.commit_field, "X-Git-Commit");
+                                ui.checkbox(&mut self.git_version.dirty_field, "X-Git-Dirty");
+                                ui.checkbox(&mut self.git_version.release, "release build");
                             });
                         }
-                        if self.prerm.file_or_text == ScriptSource::FromTextfield {
-                            let _ = ui.add(
-                                egui::TextEdit::multiline(&mut self.prerm.from_textbox)
-                                    .code_editor(),
-                            );
-                        }
-                    }
+                    });
                 });
-            });
 
-            ui.group(|ui| {
-                ui.vertical_centered_justified(|ui| {
-                    ui.label("Data folder root");
-                    ui.horizontal(|ui| {
+                let group = ui.group(|ui| {
+                    ui.vertical_centered_justified(|ui| {
+                        ui.label("Output folder");
                         ui.horizontal(|ui| {
-                            if ui.button("Set path..").clicked() {
-                                if let Some(path) = rfd::FileDialog::new().pick_folder() {
-                                    self.data_path = Some(path.display().to_string());
+                            ui.horizontal(|ui| {
+                                if ui.button("Set path..").clicked() {
+                                    if let Some(path) = self.session.pick_folder() {
+                                        self.output_path = Some(path.display().to_string());
+                                    }
                                 }
-                            }
+                            });
                         });
                     });
-                });
-                if let Some(picked_path) = &self.data_path {
-                    ui.horizontal(|ui| {
-                        ui.label("Picked path:");
-                        ui.add(egui::Label::new(RichText::new(picked_path).monospace()).wrap(true));
-                    });
-                }
-            });
-
-            ui.group(|ui| {
-                ui.vertical_centered_justified(|ui| {
-                    ui.label("Output folder");
-                    ui.horizontal(|ui| {
+                    if let Some(picked_path) = &self.output_path {
                         ui.horizontal(|ui| {
-                            if ui.button("Set path..").clicked() {
-                                if let Some(path) = rfd::FileDialog::new().pick_folder() {
-                                    self.output_path = Some(path.display().to_string());
-                                }
-                            }
+                            ui.label("Picked path:");
+                            ui.add(egui::Label::new(RichText::new(picked_path).monospace()).wrap(true));
                         });
-                    });
-                });
-                if let Some(picked_path) = &self.output_path {
+                    }
                     ui.horizontal(|ui| {
-                        ui.label("Picked path:");
-                        ui.add(egui::Label::new(RichText::new(picked_path).monospace()).wrap(true));
+                        ui.label("Package file name:");
+                        ui.text_edit_singleline(&mut self.output_name);
                     });
-                }
-            });
+                    ui.checkbox(&mut self.index_feed, "write Packages index of the output folder");
+                    ui.checkbox(&mut self.sbom, "write SPDX and CycloneDX SBOMs next to each package");
+                });
+                self.group_done(ui, Section::Output, &group.response);
 
-            ui.vertical_centered(|ui| {
-                if (self.control_file.picked_path.is_some()
-                        || self.control_file.file_or_text == ScriptSource::FromTextfield)
-                    && (self.debian_binary.enabled
-                        || self.debian_binary.picked_path.is_some()
-                        || self.debian_binary.file_or_text == ScriptSource::FromTextfield)
-                    && (!self.postinst.enabled
-                        || self.postinst.picked_path.is_some()
-                        || self.postinst.file_or_text == ScriptSource::FromTextfield)
-                    && (!self.preinst.enabled
-                        || self.preinst.picked_path.is_some()
-                        || self.preinst.file_or_text == ScriptSource::FromTextfield)
-                    && (!self.prerm.enabled
-                        || self.prerm.picked_path.is_some()
-                        || self.prerm.file_or_text == ScriptSource::FromTextfield)
-                    && self.data_path.is_some() 
-                    && self.output_path.is_some()
-                {
-                    if ui
-                        .add_sized([120., 40.], egui::Button::new("Build!").fill(Color32::BLUE))
-                        .clicked()
-                    {
-                        self.success_or_not = make_package(self);
+                ui.vertical_centered(|ui| {
+                    // a running build can be followed and cancelled whatever
+                    // happened to its inputs since it started
+                    if let Some(job) = &self.build {
+                        let report = job.progress.report();
+                        ui.add(egui::ProgressBar::new(report.fraction()).text(report.to_string()));
+                        if job.progress.is_cancelled() {
+                            ui.add_enabled(false, egui::Button::new("Cancelling..."));
+                        } else if ui.button("Cancel").clicked() {
+                            job.progress.cancel();
+                        }
                     }
-                } else {
-                    ui.add_enabled(
-                        false,
-                        egui::Button::new("Build!")
-                            .fill(Color32::DARK_GRAY)
-                            .min_size(Vec2 { x: 120., y: 40. }),
-                    );
-                };
-                match &self.success_or_not {
-                    Ok(_) => ui.label("Success!"),
-                    Err(e) => ui.label(e.to_string())
-                }
+                    let problems = validate::problems(self);
+                    if problems.is_empty() {
+                        if self.build.is_none()
+                            && ui
+                                .add_sized([120., 40.], egui::Button::new("Build!").fill(Color32::BLUE))
+                                .clicked()
+                        {
+                            self.start_build();
+                        }
+                    } else {
+                        if self.build.is_none() {
+                            ui.add_enabled(
+                                false,
+                                egui::Button::new("Build!")
+                                    .fill(Color32::DARK_GRAY)
+                                    .min_size(Vec2 { x: 120., y: 40. }),
+                            );
+                        }
+                        ui.label("Fix these before building:");
+                        for problem in &problems {
+                            if ui.link(&problem.message).clicked() {
+                                self.jump_to = Some(problem.section);
+                                // the groups are drawn above, so scroll on the next frame
+                                ui.ctx().request_repaint();
+                            }
+                        }
+                    };
+                    let mut watching = self.watcher.is_some();
+                    if ui.checkbox(&mut watching, "watch inputs and rebuild").changed() {
+                        self.watcher = None;
+                        if watching {
+                            match Watcher::new(self, DEFAULT_DEBOUNCE) {
+                                Ok(watcher) => self.watcher = Some(watcher),
+                                Err(e) => self.success_or_not = Err(e),
+                            }
+                        }
+                    }
+                    match &self.success_or_not {
+                        Ok(message) => {
+                            ui.label("Success!");
+                            ui.label(RichText::new(message).monospace());
+                        }
+                        Err(e) => error_view(ui, e),
+                    }
+                });
             });
         });
+        // dropped outside of every group
+        self.dropped = None;
     }
 }
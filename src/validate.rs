@@ -0,0 +1,150 @@
+use anyhow::{bail, Result};
+use std::path::Path;
+
+use crate::{
+    control::ControlFile,
+    ui::{FileOrPath, IpkBuilder, ScriptSource},
+    vars,
+};
+
+/// Group of the GUI a problem belongs to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Section {
+    Control,
+    DebianBinary,
+    Postinst,
+    Preinst,
+    Prerm,
+    Service,
+    Manifest,
+    DataRoot,
+    Output,
+}
+
+/// Something that keeps the package from being built.
+pub struct Problem {
+    pub section: Section,
+    pub message: String,
+}
+
+/// A file input that reads from a path needs one that exists.
+fn check_picked(file: &FileOrPath, what: &str, section: Section, problems: &mut Vec<Problem>) {
+    if file.file_or_text != ScriptSource::FromPath {
+        return;
+    }
+    let message = match &file.picked_path {
+        None => format!("No file picked for the {}", what),
+        Some(path) if !path.is_file() => format!("The {} {} does not exist", what, path.display()),
+        Some(_) => return,
+    };
+    problems.push(Problem { section, message });
+}
+
+fn check_folder(path: &Option<String>, what: &str, section: Section, problems: &mut Vec<Problem>) {
+    if let Some(path) = path {
+        if !Path::new(path).is_dir() {
+            problems.push(Problem {
+                section,
+                message: format!("The {} {} does not exist", what, path),
+            });
+        }
+    }
+}
+
+/// Everything that has to be fixed before building. The GUI runs this on
+/// every frame it draws: it reads the control file if one is picked and
+/// stats the other picked paths, but opens nothing else.
+pub fn problems(data: &IpkBuilder) -> Vec<Problem> {
+    let mut problems = Vec::new();
+
+    check_picked(&data.control_file, "control file", Section::Control, &mut problems);
+    let control = match data.control_file.file_or_text {
+        ScriptSource::FromTextfield => Some(data.control_file.from_textbox.clone()),
+        ScriptSource::FromPath => data
+            .control_file
+            .picked_path
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok()),
+    };
+    if let Some(control) = control {
+        if data.variables.enabled {
+            if let Err(e) = vars::check_names(&control, &data.variables) {
+                problems.push(Problem {
+                    section: Section::Control,
+                    message: e.to_string(),
+                });
+            }
+        }
+        for (field, message) in ControlFile::parse(&control).problems() {
+            // the version is filled in from git at build time
+            if data.git_version.enabled && field == "Version" {
+                continue;
+            }
+            problems.push(Problem {
+                section: Section::Control,
+                message: format!("{}: {}", field, message),
+            });
+        }
+    }
+
+    if !data.debian_binary.enabled {
+        check_picked(&data.debian_binary, "debian-binary", Section::DebianBinary, &mut problems);
+    }
+    for (script, name, section) in [
+        (&data.postinst, "postinst script", Section::Postinst),
+        (&data.preinst, "preinst script", Section::Preinst),
+        (&data.prerm, "prerm script", Section::Prerm),
+    ] {
+        if script.enabled {
+            check_picked(script, name, section, &mut problems);
+            if data.variables.enabled && script.file_or_text == ScriptSource::FromTextfield {
+                if let Err(e) = vars::check_names(&script.from_textbox, &data.variables) {
+                    problems.push(Problem {
+                        section,
+                        message: format!("{}: {}", name, e),
+                    });
+                }
+            }
+        }
+    }
+
+    if data.service.enabled {
+        if let Err(e) = data.service.install_file() {
+            problems.push(Problem {
+                section: Section::Service,
+                message: e.to_string(),
+            });
+        }
+    }
+    if data.manifest.enabled {
+        check_picked(&data.manifest, "install manifest", Section::Manifest, &mut problems);
+    }
+
+    if data.data_path.is_none() && !data.manifest.enabled && !data.service.enabled {
+        problems.push(Problem {
+            section: Section::DataRoot,
+            message: "No data folder picked and no install manifest used".to_owned(),
+        });
+    }
+    check_folder(&data.data_path, "data folder", Section::DataRoot, &mut problems);
+
+    if data.output_path.is_none() {
+        problems.push(Problem {
+            section: Section::Output,
+            message: "No output folder picked".to_owned(),
+        });
+    }
+    check_folder(&data.output_path, "output folder", Section::Output, &mut problems);
+    problems
+}
+
+/// Error listing the [`problems`] of `data`, if it has any. For builds that
+/// are not started from the Build button, e.g. by a watcher.
+pub fn check(data: &IpkBuilder) -> Result<()> {
+    let problems = problems(data);
+    if !problems.is_empty() {
+        let messages: Vec<String> = problems.into_iter().map(|p| p.message).collect();
+        bail!("Not building: {}", messages.join("; "));
+    }
+    Ok(())
+}
@@ -0,0 +1,203 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{control::ControlFile, git};
+
+/// `${NAME}` substitution in the control file and maintainer scripts.
+/// `$${NAME}` is written out as a literal `${NAME}`, which is how scripts
+/// use braced shell variables like `$${IPKG_INSTROOT}`. Names that are not
+/// defined are an error.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Variables {
+    pub enabled: bool,
+    /// project defined variables, one `NAME=value` per line
+    pub defined: String,
+}
+
+impl Variables {
+    pub fn parse_defined(&self) -> Result<HashMap<String, String>> {
+        let mut vars = HashMap::new();
+        for (i, line) in self.defined.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((name, value)) = line.split_once('=') else {
+                bail!("Line {} of the project variables is not NAME=value: {}", i + 1, line);
+            };
+            vars.insert(name.trim().to_owned(), value.trim().to_owned());
+        }
+        Ok(vars)
+    }
+}
+
+/// Build time, honoring `SOURCE_DATE_EPOCH` for reproducible builds.
+fn build_date() -> String {
+    let now = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|e| e.parse().ok())
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+        .unwrap_or_else(SystemTime::now);
+    humantime::format_rfc3339_seconds(now).to_string()
+}
+
+/// Prefix under which the environment is visible, `${ENV_HOME}` for `HOME`.
+pub const ENV_PREFIX: &str = "ENV_";
+
+/// Environment (as `ENV_NAME`), `BUILD_DATE`, `GIT_COMMIT` and the project
+/// variables, in increasing order of precedence.
+pub fn base_variables(settings: &Variables, git_dir: Option<&Path>) -> Result<HashMap<String, String>> {
+    let mut vars: HashMap<String, String> = env::vars()
+        .map(|(name, value)| (format!("{}{}", ENV_PREFIX, name), value))
+        .collect();
+    vars.insert("BUILD_DATE".to_owned(), build_date());
+    if let Some(commit) = git_dir.and_then(git::commit) {
+        vars.insert("GIT_COMMIT".to_owned(), commit);
+    }
+    vars.extend(settings.parse_defined()?);
+    Ok(vars)
+}
+
+/// Add `PACKAGE`, `VERSION` and `ARCH` from an already expanded control
+/// file, unless the project defines them itself.
+pub fn with_control_fields(mut vars: HashMap<String, String>, control: &str) -> HashMap<String, String> {
+    let fields = ControlFile::parse(control);
+    for (name, field) in [("PACKAGE", "Package"), ("VERSION", "Version"), ("ARCH", "Architecture")] {
+        if let Some(value) = fields.get(field) {
+            vars.entry(name.to_owned()).or_insert_with(|| value.to_owned());
+        }
+    }
+    vars
+}
+
+/// Variables the builder sets itself, depending on the build.
+const BUILTIN: &[&str] = &["BUILD_DATE", "GIT_COMMIT", "PACKAGE", "VERSION", "ARCH"];
+
+/// Check that every `${NAME}` in `text` is defined, without running git or
+/// looking at the control file, for checking the settings as they are edited.
+pub fn check_names(text: &str, settings: &Variables) -> Result<()> {
+    let mut vars: HashMap<String, String> = env::vars()
+        .map(|(name, value)| (format!("{}{}", ENV_PREFIX, name), value))
+        .collect();
+    vars.extend(BUILTIN.iter().map(|name| (name.to_string(), String::new())));
+    vars.extend(settings.parse_defined()?);
+    expand(text, &vars).map(|_| ())
+}
+
+/// Replace every `${NAME}` in `text`. A name that is not in `vars` is an
+/// error naming it.
+pub fn expand(text: &str, vars: &HashMap<String, String>) -> Result<String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with("$${") {
+            expanded.push('$');
+            rest = &rest[2..];
+            continue;
+        }
+        if !rest.starts_with("${") {
+            expanded.push('$');
+            rest = &rest[1..];
+            continue;
+        }
+        let Some(end) = rest.find('}') else {
+            bail!("Unterminated variable reference {}", rest.lines().next().unwrap_or_default());
+        };
+        let name = &rest[2..end];
+        match vars.get(name) {
+            Some(value) => expanded.push_str(value),
+            None if name.starts_with(ENV_PREFIX) => {
+                bail!("Environment variable {} is not set", &name[ENV_PREFIX.len()..])
+            }
+            None if env::var_os(name).is_some() => bail!(
+                "Unknown variable ${{{}}}, the environment is ${{{}{}}}",
+                name,
+                ENV_PREFIX,
+                name
+            ),
+            None => bail!(
+                "Unknown variable ${{{}}}, write $${{{}}} to keep it for the shell",
+                name,
+                name
+            ),
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn expands_known_variables() {
+        let vars = vars(&[("PACKAGE", "app"), ("VERSION", "1.0")]);
+        assert_eq!(expand("${PACKAGE}_${VERSION}.ipk", &vars).unwrap(), "app_1.0.ipk");
+        assert_eq!(expand("no variables, $1 and $HOME", &vars).unwrap(), "no variables, $1 and $HOME");
+    }
+
+    #[test]
+    fn escapes_are_written_out() {
+        let vars = vars(&[("PACKAGE", "app")]);
+        assert_eq!(expand("$${PACKAGE} is ${PACKAGE}", &vars).unwrap(), "${PACKAGE} is app");
+        assert_eq!(expand("$$", &vars).unwrap(), "$$");
+    }
+
+    #[test]
+    fn unknown_variables_are_errors() {
+        let vars = vars(&[("PACKAGE", "app")]);
+        let error = expand("Version: ${VERSION}", &vars).unwrap_err();
+        assert!(error.to_string().contains("${VERSION}"), "{}", error);
+        assert_eq!(
+            expand("rm -rf $${IPKG_INSTROOT}/var/lib/${PACKAGE}", &vars).unwrap(),
+            "rm -rf ${IPKG_INSTROOT}/var/lib/app"
+        );
+        let error = expand("${PATH}", &vars).unwrap_err();
+        assert!(error.to_string().contains("${ENV_PATH}"), "{}", error);
+        let error = expand("${ENV_NOT_SET_ANYWHERE}", &vars).unwrap_err();
+        assert!(error.to_string().contains("NOT_SET_ANYWHERE is not set"), "{}", error);
+    }
+
+    #[test]
+    fn names_are_checked_without_building() {
+        let settings = Variables {
+            enabled: true,
+            defined: "CHANNEL=stable\n".to_owned(),
+        };
+        assert!(check_names("Version: ${VERSION}\nSection: ${CHANNEL}\n", &settings).is_ok());
+        let error = check_names("Version: ${VERSON}\n", &settings).unwrap_err();
+        assert!(error.to_string().contains("${VERSON}"), "{}", error);
+    }
+
+    #[test]
+    fn nested_braces_end_at_the_first_brace() {
+        let vars = vars(&[("A", "a")]);
+        // shell parameter expansion is escaped like any other
+        assert_eq!(expand("$${A:-$${B}}", &vars).unwrap(), "${A:-${B}}");
+        assert!(expand("${A:-${B}}", &vars).is_err());
+        assert_eq!(expand("${A}}", &vars).unwrap(), "a}");
+        assert!(expand("${A", &vars).is_err());
+    }
+
+    #[test]
+    fn environment_is_prefixed() {
+        let base = base_variables(&Variables::default(), None).unwrap();
+        assert!(base.contains_key("BUILD_DATE"));
+        assert!(base.keys().filter(|k| *k != "BUILD_DATE").all(|k| k.starts_with(ENV_PREFIX)));
+    }
+}
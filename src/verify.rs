@@ -0,0 +1,194 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use log::warn;
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+use tar::Archive;
+
+use crate::{
+    control::{ControlFile, OPKG_FIELDS, REQUIRED_FIELDS},
+    feed::member_name,
+    manifest::InstallFile,
+};
+
+/// Members of the outer archive, in the order they are written.
+pub const MEMBERS: [&str; 3] = ["debian-binary", "control.tar.gz", "data.tar.gz"];
+
+/// A regular file in data.tar.gz.
+#[derive(Clone, Serialize)]
+pub struct PackagedFile {
+    pub path: PathBuf,
+    /// hex SHA-1 of the content, which SPDX asks for
+    pub sha1: String,
+    /// hex SHA-256 of the content
+    pub sha256: String,
+    pub size: u64,
+}
+
+/// What [`package`] found in a package that passed.
+pub struct Verification {
+    pub package: String,
+    pub version: String,
+    /// regular files in data.tar.gz, all matching their source
+    pub files: Vec<PackagedFile>,
+    pub bytes: u64,
+}
+
+impl fmt::Display for Verification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "verified {} {}: {} files, {} bytes match the sources",
+            self.package,
+            self.version,
+            self.files.len(),
+            self.bytes
+        )
+    }
+}
+
+/// Hex SHA-256 and length of everything `reader` yields.
+pub fn digest<R: Read>(mut reader: R) -> io::Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let len = io::copy(&mut reader, &mut hasher)?;
+    Ok((format!("{:x}", hasher.finalize()), len))
+}
+
+/// The control file out of control.tar.gz.
+fn read_control<R: Read>(member: R) -> Result<String> {
+    let mut archive = Archive::new(GzDecoder::new(member));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if member_name(&entry)? == "control" {
+            let mut control = String::new();
+            entry.read_to_string(&mut control).context("Control file is not UTF-8")?;
+            return Ok(control);
+        }
+    }
+    bail!("control.tar.gz has no control file")
+}
+
+/// SHA-1 and SHA-256 of the file at `path` read from `reader`.
+fn packaged_file<R: Read>(path: PathBuf, mut reader: R) -> io::Result<PackagedFile> {
+    let (mut sha1, mut sha256) = (Sha1::new(), Sha256::new());
+    let mut buffer = [0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        sha1.update(&buffer[..n]);
+        sha256.update(&buffer[..n]);
+        size += n as u64;
+    }
+    Ok(PackagedFile {
+        path,
+        sha1: format!("{:x}", sha1.finalize()),
+        sha256: format!("{:x}", sha256.finalize()),
+        size,
+    })
+}
+
+/// Checksums of the regular files in data.tar.gz. Reading them to the end
+/// also checks that everything decompresses.
+fn read_data<R: Read>(member: R) -> Result<BTreeMap<PathBuf, PackagedFile>> {
+    let mut archive = Archive::new(GzDecoder::new(member));
+    let mut files = BTreeMap::new();
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let name = PathBuf::from(member_name(&entry)?);
+        if entry.header().entry_type().is_file() {
+            let file = packaged_file(name.clone(), entry)
+                .context(format!("Could not decompress {}", name.display()))?;
+            files.insert(name, file);
+        }
+    }
+    Ok(files)
+}
+
+/// Read a freshly built package back and check it against what went into
+/// it: the member names and their order, the control file, and the content
+/// of every file in data.tar.gz.
+pub fn package(path: &Path, control: &str, files: &[InstallFile]) -> Result<Verification> {
+    let file = File::open(path).context(format!("Could not open {}", path.display()))?;
+    let mut outer = Archive::new(GzDecoder::new(file));
+    let mut members = Vec::new();
+    let mut packaged_control = None;
+    let mut data = None;
+    for entry in outer.entries().context(format!("Could not read {}", path.display()))? {
+        let mut entry = entry.context(format!("Could not read {}", path.display()))?;
+        let name = member_name(&entry)?;
+        match name.as_str() {
+            "debian-binary" => {
+                let mut version = String::new();
+                entry.read_to_string(&mut version)?;
+                if !version.trim().starts_with("2.") {
+                    bail!("debian-binary holds {:?}, expected format 2.x", version);
+                }
+            }
+            "control.tar.gz" => packaged_control = Some(read_control(entry).context("Error reading control.tar.gz")?),
+            "data.tar.gz" => data = Some(read_data(entry).context("Error reading data.tar.gz")?),
+            _ => {}
+        }
+        members.push(name);
+    }
+    if members != MEMBERS {
+        bail!("Members are {:?}, expected {:?}", members, MEMBERS);
+    }
+    let (Some(packaged_control), Some(data)) = (packaged_control, data) else {
+        bail!("Members are {:?}, expected {:?}", members, MEMBERS);
+    };
+
+    if packaged_control != control {
+        bail!("Packaged control file differs from the one built");
+    }
+    // field values are the user's business; opkg needs the fields naming
+    // the package, the others are only worth a warning
+    let fields = ControlFile::parse(&packaged_control);
+    for field in REQUIRED_FIELDS.iter().filter(|f| fields.get(f).is_none_or(|v| v.is_empty())) {
+        if OPKG_FIELDS.contains(field) {
+            bail!("Packaged control file has no {} field", field);
+        }
+        warn!("Packaged control file has no {} field", field);
+    }
+
+    // a later entry for the same path replaces an earlier one, in the
+    // archive as on the target
+    let mut expected = BTreeMap::new();
+    for file in files.iter().filter(|f| !f.is_dir && !f.is_symlink()) {
+        expected.insert(file.dest.clone(), &file.src);
+    }
+    let mut packaged_files = Vec::new();
+    let mut bytes = 0;
+    for (dest, src) in &expected {
+        let Some(packaged) = data.get(dest) else {
+            bail!("{} is missing from data.tar.gz", dest.display());
+        };
+        let source = File::open(src).context(format!("Could not open {}", src.display()))?;
+        let (source, _) = digest(source).context(format!("Could not read {}", src.display()))?;
+        if source != packaged.sha256 {
+            bail!("{} in data.tar.gz does not match {}", dest.display(), src.display());
+        }
+        bytes += packaged.size;
+        packaged_files.push(packaged.clone());
+    }
+    if let Some(extra) = data.keys().find(|name| !expected.contains_key(*name)) {
+        bail!("{} in data.tar.gz has no source", extra.display());
+    }
+
+    Ok(Verification {
+        package: fields.get("Package").unwrap_or_default().to_owned(),
+        version: fields.get("Version").unwrap_or_default().to_owned(),
+        files: packaged_files,
+        bytes,
+    })
+}
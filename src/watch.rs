@@ -0,0 +1,208 @@
+use anyhow::{Context, Result};
+use log::{debug, info};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    manifest, project_dir,
+    ui::{IpkBuilder, ScriptSource},
+};
+
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches the inputs of a build and tells when to rebuild. A rebuild is
+/// due once no change has been seen for the debounce interval.
+pub struct Watcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    /// what the watcher was set up for, to tell when it is out of date
+    inputs: Inputs,
+    /// files watched through their parent folder
+    files: Vec<PathBuf>,
+    folders: Vec<PathBuf>,
+    ignored: Option<PathBuf>,
+    debounce: Duration,
+    changed_at: Option<Instant>,
+}
+
+/// Paths a build of some settings reads, as the settings name them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Inputs {
+    pub folders: Vec<PathBuf>,
+    pub files: Vec<PathBuf>,
+    /// the output folder
+    pub ignored: Option<PathBuf>,
+}
+
+impl Inputs {
+    pub fn of(data: &IpkBuilder) -> Inputs {
+        let mut folders: Vec<PathBuf> = data.data_path.iter().map(PathBuf::from).collect();
+        let mut files = input_files(data);
+        for source in manifest_sources(data) {
+            if source.is_dir() {
+                folders.push(source);
+            } else if source.exists() {
+                files.push(source);
+            }
+        }
+        if !data.snippets.uses.is_empty() {
+            folders.extend(data.snippets.dirs.iter().filter(|d| d.is_dir()).cloned());
+        }
+        Inputs {
+            folders,
+            files,
+            ignored: data.output_path.as_ref().map(PathBuf::from),
+        }
+    }
+}
+
+/// Host paths the install manifest reads, see [`manifest::sources`].
+fn manifest_sources(data: &IpkBuilder) -> Vec<PathBuf> {
+    if !data.manifest.enabled {
+        return Vec::new();
+    }
+    if data.manifest.file_or_text == ScriptSource::FromPath {
+        let Some(path) = &data.manifest.picked_path else {
+            return Vec::new();
+        };
+        let text = fs::read_to_string(path).unwrap_or_default();
+        manifest::sources(&text, path.parent().unwrap_or(Path::new("")))
+    } else {
+        manifest::sources(&data.manifest.from_textbox, &project_dir(data))
+    }
+}
+
+/// Input files of the build that are read from disk, the project file and
+/// the shlibdeps indexes included.
+fn input_files(data: &IpkBuilder) -> Vec<PathBuf> {
+    [
+        (&data.control_file, true),
+        // `enabled` means the default debian-binary here
+        (&data.debian_binary, !data.debian_binary.enabled),
+        (&data.postinst, data.postinst.enabled),
+        (&data.preinst, data.preinst.enabled),
+        (&data.prerm, data.prerm.enabled),
+        (&data.manifest, data.manifest.enabled),
+    ]
+    .into_iter()
+    .filter(|(f, used)| *used && f.file_or_text == ScriptSource::FromPath)
+    .map(|(f, _)| f)
+    .filter_map(|f| f.picked_path.clone())
+    .chain(data.service.file.clone().filter(|_| data.service.enabled))
+    .chain(
+        [&data.shlibdeps.feed_index, &data.shlibdeps.contents_index, &data.shlibdeps.soname_map]
+            .into_iter()
+            .flatten()
+            .filter(|_| data.shlibdeps.enabled)
+            .cloned(),
+    )
+    .chain(data.project_path.clone())
+    .collect()
+}
+
+impl Watcher {
+    pub fn new(data: &IpkBuilder, debounce: Duration) -> Result<Self> {
+        let (tx, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx).context("Could not start file watcher")?;
+        // events come with absolute paths, so compare against canonical ones
+        let canonical = |p: &Path| fs::canonicalize(p).unwrap_or_else(|_| p.to_owned());
+        let inputs = Inputs::of(data);
+        let folders: Vec<PathBuf> = inputs.folders.iter().map(|p| canonical(p)).collect();
+        for folder in &folders {
+            watcher
+                .watch(folder, RecursiveMode::Recursive)
+                .context(format!("Could not watch {}", folder.display()))?;
+        }
+        let files: Vec<PathBuf> = inputs.files.iter().map(|p| canonical(p)).collect();
+        // editors replace files on save, so watch the folder a file is in
+        for file in &files {
+            let parent = file.parent().unwrap_or(Path::new("."));
+            watcher
+                .watch(parent, RecursiveMode::NonRecursive)
+                .context(format!("Could not watch {}", file.display()))?;
+        }
+        info!(
+            "Watching {} folders and {} files for changes",
+            folders.len(),
+            files.len()
+        );
+        // don't rebuild because the build wrote into the watched tree
+        let ignored = inputs.ignored.as_deref().map(canonical);
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            inputs,
+            files,
+            folders,
+            ignored,
+            debounce,
+            changed_at: None,
+        })
+    }
+
+    /// True while `data` reads the paths the watcher was set up for. Once
+    /// it is false, a new watcher is needed.
+    pub fn watches(&self, data: &IpkBuilder) -> bool {
+        self.inputs == Inputs::of(data)
+    }
+
+    fn is_input(&self, path: &Path) -> bool {
+        if let Some(ignored) = &self.ignored {
+            if path.starts_with(ignored) {
+                return false;
+            }
+        }
+        self.files.iter().any(|f| f == path) || self.folders.iter().any(|f| path.starts_with(f))
+    }
+
+    /// Drain pending events and return true if a rebuild is due.
+    pub fn poll(&mut self) -> bool {
+        while let Ok(event) = self.events.try_recv() {
+            let Ok(event) = event else {
+                continue;
+            };
+            if matches!(event.kind, EventKind::Access(_)) {
+                continue;
+            }
+            if event.paths.iter().any(|p| self.is_input(p)) {
+                debug!("Change in {:?}", event.paths);
+                self.changed_at = Some(Instant::now());
+            }
+        }
+        match self.changed_at {
+            Some(changed_at) if changed_at.elapsed() >= self.debounce => {
+                self.changed_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inputs_follow_the_settings() {
+        let mut data = IpkBuilder::default();
+        data.control_file.file_or_text = ScriptSource::FromPath;
+        data.control_file.picked_path = Some(PathBuf::from("/p/control"));
+        data.postinst.file_or_text = ScriptSource::FromPath;
+        data.postinst.picked_path = Some(PathBuf::from("/p/postinst"));
+        data.project_path = Some(PathBuf::from("/p/app.toml"));
+        data.data_path = Some("/p/root".to_owned());
+        let inputs = Inputs::of(&data);
+        // postinst is not enabled, so it is not read
+        assert_eq!(inputs.files, [PathBuf::from("/p/control"), PathBuf::from("/p/app.toml")]);
+        assert_eq!(inputs.folders, [PathBuf::from("/p/root")]);
+
+        data.postinst.enabled = true;
+        assert_ne!(Inputs::of(&data), inputs);
+    }
+}
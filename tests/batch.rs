@@ -0,0 +1,61 @@
+mod common;
+
+use std::{fs, path::PathBuf};
+
+use ipkbuilder::{batch, project};
+
+use common::{builder, scratch};
+
+#[test]
+fn same_output_is_refused() {
+    let dir = scratch("batch-same-output");
+    let first = dir.join("first.toml");
+    let second = dir.join("second.toml");
+    project::save(&builder(&dir), &first).unwrap();
+    project::save(&builder(&dir), &second).unwrap();
+    let error = batch::check_outputs(&[first.clone(), second.clone()]).unwrap_err();
+    assert!(error.to_string().contains("both write"), "{}", error);
+    assert!(error.to_string().contains("outpackage.ipk"), "{}", error);
+}
+
+#[test]
+fn distinct_outputs_build_in_parallel() {
+    let dir = scratch("batch-distinct-outputs");
+    let projects: Vec<PathBuf> = ["first", "second"]
+        .iter()
+        .map(|name| {
+            let mut data = builder(&dir);
+            data.output_name = format!("{}.ipk", name);
+            let project = dir.join(format!("{}.toml", name));
+            project::save(&data, &project).unwrap();
+            project
+        })
+        .collect();
+    batch::check_outputs(&projects).unwrap();
+    let results = batch::run(&projects, 2);
+    assert_eq!(results.len(), 2);
+    for (result, name) in results.iter().zip(["first.ipk", "second.ipk"]) {
+        assert_eq!(result.error, None);
+        assert_eq!(result.output, Some(dir.join(name)));
+    }
+}
+
+#[test]
+fn folder_batch_skips_other_toml_and_indexes_the_feed() {
+    let dir = scratch("batch-folder");
+    fs::write(dir.join("Cargo.toml"), "[package]\nname = \"app\"\nversion = \"1.0.0\"\n").unwrap();
+    let mut data = builder(&dir);
+    data.index_feed = true;
+    project::save(&data, dir.join("app.toml")).unwrap();
+    let projects = batch::collect_projects(std::slice::from_ref(&dir)).unwrap();
+    assert_eq!(projects, [dir.join("app.toml")]);
+
+    let results = batch::run(&projects, 1);
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].status, batch::Status::Ok);
+    let size = fs::metadata(dir.join("outpackage.ipk")).unwrap().len();
+    assert_eq!(results[0].size, Some(size));
+    assert!(fs::read_to_string(dir.join("Packages")).unwrap().contains("Filename: outpackage.ipk"));
+    let json = batch::to_json(&results).unwrap();
+    assert!(json.contains("\"status\": \"ok\""), "{}", json);
+}
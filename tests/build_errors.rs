@@ -0,0 +1,160 @@
+mod common;
+
+use std::{fs, path::PathBuf};
+
+use ipkbuilder::{
+    make_package,
+    progress::Progress,
+    ui::{IpkBuilder, ScriptSource},
+};
+
+use common::{builder, scratch};
+
+/// Build and return the error with its whole cause chain.
+fn build_error(data: &IpkBuilder) -> String {
+    match make_package(data, &Progress::default()) {
+        Ok(package) => panic!("build succeeded: {}", package),
+        Err(e) => format!("{:#}", e),
+    }
+}
+
+#[test]
+fn builds_without_errors() {
+    let out = scratch("ok");
+    let package = make_package(&builder(&out), &Progress::default()).unwrap();
+    assert_eq!(PathBuf::from(package), out.join("outpackage.ipk"));
+    // the package, its checksum and its build record
+    assert_eq!(fs::read_dir(&out).unwrap().count(), 3);
+    let checksum = fs::read_to_string(out.join("outpackage.ipk.sha256")).unwrap();
+    assert!(checksum.ends_with("  outpackage.ipk\n"), "{}", checksum);
+    assert!(out.join("outpackage.ipk.json").is_file());
+}
+
+#[test]
+fn missing_output_folder() {
+    let mut data = builder(&scratch("no-output"));
+    data.output_path = None;
+    assert!(build_error(&data).contains("No output folder picked"));
+}
+
+#[test]
+fn output_folder_does_not_exist() {
+    let out = scratch("output-gone").join("gone");
+    let error = build_error(&builder(&out));
+    assert!(error.contains("Could not create"), "{}", error);
+    assert!(error.contains(&out.display().to_string()), "{}", error);
+}
+
+#[test]
+fn control_file_not_picked() {
+    let mut data = builder(&scratch("control-not-picked"));
+    data.control_file.file_or_text = ScriptSource::FromPath;
+    assert!(build_error(&data).contains("No file picked for the control file"));
+}
+
+#[test]
+fn control_file_unreadable() {
+    let dir = scratch("control-unreadable");
+    let mut data = builder(&dir);
+    data.control_file.file_or_text = ScriptSource::FromPath;
+    data.control_file.picked_path = Some(dir.join("control"));
+    let error = build_error(&data);
+    assert!(error.contains("Could not read control file"), "{}", error);
+    assert!(error.contains(&dir.join("control").display().to_string()), "{}", error);
+}
+
+#[test]
+fn script_not_picked() {
+    let mut data = builder(&scratch("script-not-picked"));
+    data.postinst.enabled = true;
+    data.postinst.file_or_text = ScriptSource::FromPath;
+    assert!(build_error(&data).contains("No file picked for the postinst script"));
+}
+
+#[test]
+fn script_unreadable() {
+    let dir = scratch("script-unreadable");
+    let mut data = builder(&dir);
+    data.prerm.enabled = true;
+    data.prerm.file_or_text = ScriptSource::FromPath;
+    data.prerm.picked_path = Some(dir.join("prerm"));
+    let error = build_error(&data);
+    assert!(error.contains("Could not read prerm script"), "{}", error);
+    // nothing is left behind in the output folder
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+}
+
+#[test]
+fn debian_binary_not_picked() {
+    let mut data = builder(&scratch("debian-binary-not-picked"));
+    data.debian_binary.enabled = false;
+    data.debian_binary.file_or_text = ScriptSource::FromPath;
+    assert!(build_error(&data).contains("No file picked for the debian-binary"));
+}
+
+#[test]
+fn manifest_unreadable() {
+    let dir = scratch("manifest-unreadable");
+    let mut data = builder(&dir);
+    data.manifest.enabled = true;
+    data.manifest.file_or_text = ScriptSource::FromPath;
+    data.manifest.picked_path = Some(dir.join("manifest"));
+    assert!(build_error(&data).contains("Could not read manifest"));
+}
+
+#[test]
+fn manifest_source_missing() {
+    let mut data = builder(&scratch("manifest-source-missing"));
+    data.manifest.enabled = true;
+    data.manifest.from_textbox = "build/app -> /usr/bin/app\n".to_owned();
+    let error = build_error(&data);
+    assert!(error.contains("Manifest line 1"), "{}", error);
+    assert!(error.contains("Could not read"), "{}", error);
+}
+
+#[test]
+fn data_folder_missing() {
+    let dir = scratch("data-missing");
+    let mut data = builder(&dir);
+    data.data_path = Some(dir.join("root").display().to_string());
+    let error = build_error(&data);
+    assert!(error.contains("Could not walk data folder"), "{}", error);
+}
+
+#[test]
+fn cancelled_build_leaves_no_output() {
+    let dir = scratch("cancelled");
+    let root = dir.join("root");
+    fs::create_dir(&root).unwrap();
+    fs::write(root.join("file"), "content").unwrap();
+    let out = dir.join("out");
+    fs::create_dir(&out).unwrap();
+    let mut data = builder(&out);
+    data.data_path = Some(root.display().to_string());
+    let progress = Progress::default();
+    progress.cancel();
+    let error = make_package(&data, &progress).unwrap_err();
+    assert!(format!("{:#}", error).contains("Build cancelled"));
+    assert_eq!(fs::read_dir(&out).unwrap().count(), 0);
+}
+
+#[test]
+fn failed_rebuild_keeps_the_previous_package() {
+    let out = scratch("failed-rebuild");
+    let mut data = builder(&out);
+    make_package(&data, &Progress::default()).unwrap();
+    let package = fs::read(out.join("outpackage.ipk")).unwrap();
+    let checksum = fs::read_to_string(out.join("outpackage.ipk.sha256")).unwrap();
+    data.postinst.enabled = true;
+    data.postinst.file_or_text = ScriptSource::FromPath;
+    data.postinst.picked_path = Some(out.join("postinst"));
+    build_error(&data);
+    let progress = Progress::default();
+    progress.cancel();
+    assert!(make_package(&builder(&out), &progress).is_err());
+    assert_eq!(fs::read(out.join("outpackage.ipk")).unwrap(), package);
+    assert_eq!(fs::read_to_string(out.join("outpackage.ipk.sha256")).unwrap(), checksum);
+    assert!(out.join("outpackage.ipk.json").is_file());
+    // and nothing partial is left next to it
+    assert_eq!(fs::read_dir(&out).unwrap().count(), 3);
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use std::{fs, path::Path};
+
+use ipkbuilder::{build_all, cargo::CargoProject, progress::Progress};
+
+use common::scratch;
+
+#[test]
+fn packages_cargo_project() {
+    let dir = scratch("cargo");
+    fs::write(
+        dir.join("Cargo.toml"),
+        r#"[package]
+name = "app"
+version = "1.2.0"
+description = "An app"
+authors = ["Jo Doe <jo@example.com>"]
+license = "MIT"
+
+[package.metadata.ipk]
+arch = "armhf"
+depends = "libc"
+assets = [["bin/app", "/usr/bin/app", "755"]]
+"#,
+    )
+    .unwrap();
+    fs::create_dir(dir.join("bin")).unwrap();
+    fs::write(dir.join("bin/app"), "binary").unwrap();
+    let mut data = CargoProject::load(&dir.join("Cargo.toml")).unwrap().builder().unwrap();
+    data.output_path = Some(dir.display().to_string());
+    let built = build_all(&data, &Progress::default()).unwrap();
+    assert_eq!(built[0].path, dir.join("app_1.2.0_armhf.ipk"));
+    assert!(built[0].control.contains("Depends: libc\n"), "{}", built[0].control);
+    assert_eq!(built[0].verification.files[0].path, Path::new("usr/bin/app"));
+}
+
+#[test]
+fn packages_for_a_target_triple() {
+    let dir = scratch("cargo-target");
+    fs::write(
+        dir.join("Cargo.toml"),
+        r#"[package]
+name = "my_daemon"
+version = "0.3.0-rc.1"
+description = "A daemon"
+authors = ["Jo Doe <jo@example.com>"]
+"#,
+    )
+    .unwrap();
+    fs::create_dir(dir.join("src")).unwrap();
+    fs::write(dir.join("src/main.rs"), "fn main() {}").unwrap();
+    let mut project = CargoProject::load(&dir.join("Cargo.toml")).unwrap();
+    project.target_dir = dir.join("target");
+    project.target = Some("armv7-unknown-linux-gnueabihf".to_owned());
+    let release = dir.join("target/armv7-unknown-linux-gnueabihf/release");
+    fs::create_dir_all(&release).unwrap();
+    fs::write(release.join("my_daemon"), "binary").unwrap();
+    let mut data = project.builder().unwrap();
+    data.output_path = Some(dir.display().to_string());
+    let built = build_all(&data, &Progress::default()).unwrap();
+    assert_eq!(built[0].path, dir.join("my-daemon_0.3.0~rc.1_armv7.ipk"));
+    assert_eq!(built[0].verification.files[0].path, Path::new("usr/bin/my_daemon"));
+}
+
+#[test]
+fn finds_binaries_like_cargo() {
+    let dir = scratch("cargo-bins");
+    fs::write(
+        dir.join("Cargo.toml"),
+        r#"[package]
+name = "tools"
+version = "1.0.0"
+description = "Some tools"
+authors = ["Jo Doe <jo@example.com>"]
+
+[[bin]]
+name = "renamed"
+path = "src/bin/old.rs"
+"#,
+    )
+    .unwrap();
+    fs::create_dir_all(dir.join("src/bin/server")).unwrap();
+    for file in ["src/bin/old.rs", "src/bin/client.rs", "src/bin/server/main.rs", "src/bin/server/util.rs"] {
+        fs::write(dir.join(file), "fn main() {}").unwrap();
+    }
+    let mut project = CargoProject::load(&dir.join("Cargo.toml")).unwrap();
+    project.target_dir = dir.join("target");
+    let data = project.builder().unwrap();
+    let installed: Vec<&str> = data
+        .manifest
+        .from_textbox
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(2))
+        .collect();
+    assert_eq!(installed, ["/usr/bin/renamed", "/usr/bin/client", "/usr/bin/server"]);
+
+    // without any binary there is nothing to install by default
+    fs::remove_dir_all(dir.join("src")).unwrap();
+    fs::write(dir.join("Cargo.toml"), "[package]\nname = \"tools\"\ndescription = \"x\"\nauthors = [\"Jo <jo@example.com>\"]\n").unwrap();
+    let project = CargoProject::load(&dir.join("Cargo.toml")).unwrap();
+    assert!(project.builder().is_err());
+}
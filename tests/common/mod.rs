@@ -0,0 +1,26 @@
+// each test crate uses only some of these
+#![allow(dead_code)]
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use ipkbuilder::ui::{IpkBuilder, ScriptSource};
+
+/// Empty scratch folder for one test.
+pub fn scratch(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("ipkbuilder-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Settings that build fine into `out`.
+pub fn builder(out: &Path) -> IpkBuilder {
+    let mut data = IpkBuilder::default();
+    data.control_file.file_or_text = ScriptSource::FromTextfield;
+    data.output_path = Some(out.display().to_string());
+    data
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use flate2::read::GzDecoder;
+use ipkbuilder::{archive, convert, feed, make_package, progress::Progress};
+
+use common::{builder, scratch};
+
+#[test]
+fn converts_to_deb_and_back() {
+    let out = scratch("convert");
+    make_package(&builder(&out), &Progress::default()).unwrap();
+    let options = convert::Options {
+        architectures: vec![("varam335x".to_owned(), "armhf".to_owned())],
+        drop_fields: vec!["Priority".to_owned()],
+    };
+    let deb = out.join("example.deb");
+    convert::convert(&out.join("outpackage.ipk"), &deb, &options).unwrap();
+    assert!(fs::read(&deb).unwrap().starts_with(b"!<arch>\n"));
+    let ipk = out.join("example.ipk");
+    convert::convert(&deb, &ipk, &convert::Options::default()).unwrap();
+    let control = feed::package_control(&ipk).unwrap();
+    assert!(control.contains("Architecture: armhf\n"), "{}", control);
+    assert!(!control.contains("Priority"), "{}", control);
+}
+
+/// Build a deb with dpkg-deb, compressing its members with `compression`.
+/// None if dpkg-deb is not installed.
+fn dpkg_deb(dir: &Path, compression: &str) -> Option<PathBuf> {
+    let root = dir.join("root");
+    fs::create_dir_all(root.join("DEBIAN")).unwrap();
+    fs::create_dir_all(root.join("usr/bin")).unwrap();
+    fs::write(
+        root.join("DEBIAN/control"),
+        "Package: app\nVersion: 1.0\nArchitecture: all\nMaintainer: Me <me@example.com>\nDescription: The app\n",
+    )
+    .unwrap();
+    fs::write(root.join("usr/bin/app"), "#!/bin/sh\n").unwrap();
+    let deb = dir.join("app.deb");
+    let output = Command::new("dpkg-deb")
+        .arg(format!("-Z{}", compression))
+        .arg("--build")
+        .arg(&root)
+        .arg(&deb)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => Some(deb),
+        Ok(output) => panic!("dpkg-deb failed: {}", String::from_utf8_lossy(&output.stderr)),
+        Err(_) => {
+            eprintln!("dpkg-deb is not installed, skipping");
+            None
+        }
+    }
+}
+
+/// Content of `name` in the data.tar.gz of `ipk`.
+fn data_file(ipk: &Path, name: &str) -> String {
+    let members = archive::read(ipk).unwrap();
+    let mut data = tar::Archive::new(GzDecoder::new(&members.data[..]));
+    for entry in data.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        if entry.path().unwrap().ends_with(name) {
+            let mut content = String::new();
+            entry.read_to_string(&mut content).unwrap();
+            return content;
+        }
+    }
+    panic!("{} has no {}", ipk.display(), name);
+}
+
+#[test]
+fn converts_xz_and_zstd_debs() {
+    for compression in ["xz", "zstd"] {
+        let dir = scratch(&format!("convert-{}", compression));
+        let Some(deb) = dpkg_deb(&dir, compression) else {
+            return;
+        };
+        let ipk = dir.join("app.ipk");
+        convert::convert(&deb, &ipk, &convert::Options::default()).unwrap();
+        let control = feed::package_control(&ipk).unwrap();
+        assert!(control.contains("Package: app\n"), "{}", control);
+        assert_eq!(data_file(&ipk, "usr/bin/app"), "#!/bin/sh\n");
+    }
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use std::{fs, path::PathBuf};
+
+use ipkbuilder::{
+    project,
+    ui::{IpkBuilder, ScriptSource},
+};
+
+use common::scratch;
+
+fn load_error(file: &std::path::Path) -> String {
+    match project::load(file) {
+        Ok(_) => panic!("{} loaded", file.display()),
+        Err(e) => format!("{:#}", e),
+    }
+}
+
+#[test]
+fn round_trips_with_relative_paths() {
+    let dir = scratch("project-round-trip");
+    let mut data = IpkBuilder {
+        data_path: Some(dir.join("root").display().to_string()),
+        output_path: Some(dir.join("out").display().to_string()),
+        output_name: "${PACKAGE}.ipk".to_owned(),
+        ..Default::default()
+    };
+    data.postinst.enabled = true;
+    data.postinst.file_or_text = ScriptSource::FromPath;
+    data.postinst.picked_path = Some(dir.join("scripts/postinst"));
+    let file = dir.join("app.toml");
+    project::save(&data, &file).unwrap();
+
+    let text = fs::read_to_string(&file).unwrap();
+    assert!(text.starts_with("version = 1\n"), "{}", text);
+    assert!(text.contains("data_path = \"root\""), "{}", text);
+    assert!(!text.contains(&dir.display().to_string()), "{}", text);
+
+    // paths resolve against the project file wherever it was loaded from
+    let moved = scratch("project-moved");
+    fs::copy(&file, moved.join("app.toml")).unwrap();
+    let loaded = project::load(moved.join("app.toml")).unwrap();
+    assert_eq!(loaded.data_path, Some(moved.join("root").display().to_string()));
+    assert_eq!(loaded.postinst.picked_path, Some(moved.join("scripts/postinst")));
+    assert!(loaded.postinst.enabled);
+    assert_eq!(loaded.output_name, "${PACKAGE}.ipk");
+    assert_eq!(loaded.project_path, Some(moved.join("app.toml")));
+}
+
+#[test]
+fn refuses_newer_and_foreign_files() {
+    let dir = scratch("project-refused");
+    let newer = dir.join("newer.toml");
+    fs::write(&newer, "version = 99\n").unwrap();
+    let error = load_error(&newer);
+    assert!(error.contains("newer version"), "{}", error);
+
+    let cargo = dir.join("Cargo.toml");
+    fs::write(&cargo, "[package]\nname = \"app\"\n").unwrap();
+    let error = load_error(&cargo);
+    assert!(error.contains("is not a project file"), "{}", error);
+    assert!(load_error(&PathBuf::from("missing.toml")).contains("Could not read project file"));
+}
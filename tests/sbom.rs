@@ -0,0 +1,28 @@
+mod common;
+
+use std::fs;
+
+use ipkbuilder::{make_package, progress::Progress};
+
+use common::{builder, scratch};
+
+#[test]
+fn sboms_list_files_and_dependencies() {
+    let dir = scratch("sbom");
+    let root = dir.join("root");
+    fs::create_dir_all(root.join("usr/bin")).unwrap();
+    fs::write(root.join("usr/bin/app"), "binary").unwrap();
+    let out = dir.join("out");
+    fs::create_dir(&out).unwrap();
+    let mut data = builder(&out);
+    data.data_path = Some(root.display().to_string());
+    data.sbom = true;
+    make_package(&data, &Progress::default()).unwrap();
+    let spdx = fs::read_to_string(out.join("outpackage.ipk.spdx.json")).unwrap();
+    assert!(spdx.contains("\"SPDX-2.3\""), "{}", spdx);
+    assert!(spdx.contains("./usr/bin/app"), "{}", spdx);
+    assert!(spdx.contains("DEPENDS_ON"), "{}", spdx);
+    let cyclonedx = fs::read_to_string(out.join("outpackage.ipk.cdx.json")).unwrap();
+    assert!(cyclonedx.contains("\"other_package\""), "{}", cyclonedx);
+    assert!(cyclonedx.contains("user@domain.tld"), "{}", cyclonedx);
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use std::{fs, io::Read, path::Path};
+
+use flate2::read::GzDecoder;
+use ipkbuilder::{build_all, progress::Progress, split::SplitPackage, ui::ScriptSource};
+use tar::Archive;
+
+use common::{builder, scratch};
+
+/// Member names and the control file of a package's control.tar.gz.
+fn control_members(package: &Path) -> (Vec<String>, String) {
+    let members = ipkbuilder::archive::read(package).unwrap();
+    let mut names = Vec::new();
+    let mut control = String::new();
+    for entry in Archive::new(GzDecoder::new(&members.control[..])).entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let name = entry.path().unwrap().display().to_string();
+        if name == "control" {
+            entry.read_to_string(&mut control).unwrap();
+        }
+        names.push(name);
+    }
+    (names, control)
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn split_packages_get_their_own_scripts_and_depends() {
+    let dir = scratch("split-scripts");
+    let root = dir.join("root");
+    fs::create_dir_all(root.join("usr/bin")).unwrap();
+    fs::create_dir_all(root.join("usr/share/doc/app")).unwrap();
+    // any small binary linked against glibc does
+    fs::copy("/bin/sh", root.join("usr/bin/app")).unwrap();
+    fs::write(root.join("usr/share/doc/app/README"), "read me\n").unwrap();
+    let soname_map = dir.join("sonames");
+    fs::write(&soname_map, "libc.so.6 libc6\n").unwrap();
+    let out = dir.join("out");
+    fs::create_dir(&out).unwrap();
+
+    let mut data = builder(&out);
+    data.data_path = Some(root.display().to_string());
+    data.control_file.from_textbox = "Package: app\nVersion: 1.0\nArchitecture: all\n".to_owned();
+    data.postinst.enabled = true;
+    data.postinst.file_or_text = ScriptSource::FromTextfield;
+    data.postinst.from_textbox = "#!/bin/sh\necho installed\n".to_owned();
+    data.shlibdeps.enabled = true;
+    data.shlibdeps.soname_map = Some(soname_map);
+    data.split_packages = vec![
+        SplitPackage {
+            name: "app-doc".to_owned(),
+            files: "/usr/share/doc".to_owned(),
+            ..Default::default()
+        },
+        SplitPackage {
+            name: "app".to_owned(),
+            files: "/usr".to_owned(),
+            ..Default::default()
+        },
+    ];
+    let built = build_all(&data, &Progress::default()).unwrap();
+    assert_eq!(built.len(), 2);
+
+    let (names, control) = control_members(&out.join("app-doc.ipk"));
+    assert_eq!(names, ["control"]);
+    assert!(control.contains("Package: app-doc\n"), "{}", control);
+    assert!(!control.contains("Depends"), "{}", control);
+
+    let (names, control) = control_members(&out.join("app.ipk"));
+    assert!(names.contains(&"postinst".to_owned()), "{:?}", names);
+    assert!(control.contains("Depends: libc6"), "{}", control);
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use std::fs;
+
+use ipkbuilder::{build_all, progress::Progress};
+
+use common::{builder, scratch};
+
+#[test]
+fn built_package_is_verified() {
+    let dir = scratch("verified");
+    let root = dir.join("root");
+    fs::create_dir_all(root.join("etc")).unwrap();
+    fs::write(root.join("etc/app.conf"), "key = value\n").unwrap();
+    let out = dir.join("out");
+    fs::create_dir(&out).unwrap();
+    let mut data = builder(&out);
+    data.data_path = Some(root.display().to_string());
+    let built = build_all(&data, &Progress::default()).unwrap();
+    assert_eq!(built.len(), 1);
+    assert_eq!(built[0].verification.package, "example_package");
+    assert_eq!(built[0].verification.files.len(), 1);
+    assert_eq!(built[0].verification.bytes, 12);
+}
+
+#[test]
+fn only_the_fields_opkg_needs_are_required() {
+    let out = scratch("verify-fields");
+    let mut data = builder(&out);
+    data.control_file.from_textbox = "Package: app\nVersion: 1.0\nArchitecture: all\n".to_owned();
+    build_all(&data, &Progress::default()).unwrap();
+    data.control_file.from_textbox = "Package: app\nVersion: 1.0\nDescription: The app\n".to_owned();
+    let Err(error) = build_all(&data, &Progress::default()) else {
+        panic!("package without Architecture built");
+    };
+    assert!(format!("{:#}", error).contains("no Architecture field"), "{:#}", error);
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_are_packaged_as_symlinks() {
+    use flate2::read::GzDecoder;
+    use std::{os::unix::fs::symlink, path::PathBuf};
+    use tar::{Archive, EntryType};
+
+    let dir = scratch("symlinks");
+    let root = dir.join("root");
+    fs::create_dir_all(root.join("usr/lib/app")).unwrap();
+    fs::write(root.join("usr/lib/libapp.so.1"), "library").unwrap();
+    symlink("libapp.so.1", root.join("usr/lib/libapp.so")).unwrap();
+    symlink("lib/app", root.join("usr/share")).unwrap();
+    let out = dir.join("out");
+    fs::create_dir(&out).unwrap();
+    let mut data = builder(&out);
+    data.data_path = Some(root.display().to_string());
+    let built = build_all(&data, &Progress::default()).unwrap();
+    assert_eq!(built[0].verification.files.len(), 1);
+
+    let members = ipkbuilder::archive::read(&built[0].path).unwrap();
+    let mut links = Vec::new();
+    for entry in Archive::new(GzDecoder::new(&members.data[..])).entries().unwrap() {
+        let entry = entry.unwrap();
+        if entry.header().entry_type() == EntryType::Symlink {
+            let path = entry.path().unwrap().into_owned();
+            links.push((path, entry.link_name().unwrap().unwrap().into_owned()));
+        }
+    }
+    links.sort();
+    assert_eq!(
+        links,
+        [
+            (PathBuf::from("usr/lib/libapp.so"), PathBuf::from("libapp.so.1")),
+            (PathBuf::from("usr/share"), PathBuf::from("lib/app")),
+        ]
+    );
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use std::{
+    fs, thread,
+    time::{Duration, Instant},
+};
+
+use ipkbuilder::{project, validate, watch::Watcher};
+
+use common::{builder, scratch};
+
+/// Poll until the watcher asks for a rebuild, for at most five seconds.
+fn rebuild_due(watcher: &mut Watcher) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(5) {
+        if watcher.poll() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
+
+#[test]
+fn project_file_changes_trigger_a_rebuild() {
+    let dir = scratch("watch-project");
+    let out = dir.join("out");
+    fs::create_dir(&out).unwrap();
+    let file = dir.join("app.toml");
+    project::save(&builder(&out), &file).unwrap();
+    let data = project::load(&file).unwrap();
+    let mut watcher = Watcher::new(&data, Duration::from_millis(10)).unwrap();
+    assert!(watcher.watches(&data));
+    project::save(&data, &file).unwrap();
+    assert!(rebuild_due(&mut watcher));
+}
+
+#[test]
+fn watcher_goes_stale_when_inputs_change() {
+    let dir = scratch("watch-stale");
+    let root = dir.join("root");
+    fs::create_dir(&root).unwrap();
+    let mut data = builder(&dir.join("out"));
+    let watcher = Watcher::new(&data, Duration::from_millis(10)).unwrap();
+    data.data_path = Some(root.display().to_string());
+    assert!(!watcher.watches(&data));
+
+    let mut watcher = Watcher::new(&data, Duration::from_millis(10)).unwrap();
+    fs::write(root.join("file"), "content").unwrap();
+    assert!(rebuild_due(&mut watcher));
+}
+
+#[test]
+fn watch_builds_are_validated_first() {
+    let dir = scratch("watch-validate");
+    let mut data = builder(&dir.join("gone"));
+    data.data_path = Some(dir.display().to_string());
+    let Err(error) = validate::check(&data) else {
+        panic!("missing output folder passed");
+    };
+    assert!(error.to_string().contains("output folder"), "{}", error);
+    data.output_path = Some(dir.display().to_string());
+    assert!(validate::check(&data).is_ok());
+}
+
+#[test]
+fn manifest_sources_trigger_a_rebuild() {
+    let dir = scratch("watch-manifest");
+    fs::create_dir_all(dir.join("build/share")).unwrap();
+    fs::write(dir.join("build/app"), "binary").unwrap();
+    fs::write(dir.join("build/share/app.conf"), "a = 1").unwrap();
+    let mut data = builder(&dir.join("out"));
+    data.project_path = Some(dir.join("app.toml"));
+    data.manifest.enabled = true;
+    data.manifest.from_textbox = "build/app -> /usr/bin/app\nbuild/share/*.conf -> /etc/\n".to_owned();
+    let mut watcher = Watcher::new(&data, Duration::from_millis(10)).unwrap();
+    assert!(watcher.watches(&data));
+    fs::write(dir.join("build/app"), "new binary").unwrap();
+    assert!(rebuild_due(&mut watcher));
+    fs::write(dir.join("build/share/app.conf"), "a = 2").unwrap();
+    assert!(rebuild_due(&mut watcher));
+}